@@ -0,0 +1,630 @@
+//! The binary log format, written by [`BinaryWriter`](crate::BinaryWriter) and read by
+//! [`BinaryReader`].
+//!
+//! A stream starts with a header:
+//!
+//! - the magic bytes `QLOG`
+//! - the format version, as a byte
+//! - the number of metadata entries, followed by each entry as a key string and a value string
+//!
+//! Followed by records, each being its length followed by:
+//!
+//! - the time, as little-endian `i64` nanoseconds since the unix epoch
+//! - the difference between the last time and the time, in nanoseconds
+//! - the repeat count
+//! - the level, as a byte: the severity of built-in levels, or 255 for custom levels followed by
+//!   their severity byte and their name as a static string
+//! - the target, as a static string
+//! - the file and line of the location
+//! - the message, as a byte saying how it's stored followed by either its text as a string (0)
+//!   or, for logs created with [`log_interned!`](crate::log_interned), their format string as a
+//!   static string and their encoded arguments as a length followed by bytes (1)
+//! - the number of fields, followed by each field as its name as a static string, a type byte
+//!   and the value
+//!
+//! Integers are LEB128 varints (zigzag encoded if signed) unless stated otherwise, and strings are
+//! their length followed by their UTF-8 bytes. Static strings (targets, files, field names and
+//! custom level names) are interned: they're written as an index into the table of static strings
+//! seen so far in the stream, and if the index is the length of the table, the string follows and
+//! is appended to it.
+
+use crate::{
+    content::Content,
+    intern::intern,
+    interned::{self, Packed, PackedArgs},
+    Field, Level, Location, Log, Value,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+};
+
+pub(crate) const MAGIC: &[u8; 4] = b"QLOG";
+pub(crate) const VERSION: u8 = 1;
+
+const MESSAGE_TEXT: u8 = 0;
+const MESSAGE_INTERNED: u8 = 1;
+
+const CUSTOM_LEVEL: u8 = 255;
+
+const TAG_I64: u8 = 0;
+const TAG_U64: u8 = 1;
+const TAG_F64: u8 = 2;
+const TAG_BOOL: u8 = 3;
+const TAG_STR: u8 = 4;
+const TAG_HEX: u8 = 5;
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_signed(buf: &mut Vec<u8>, value: i64) {
+    write_varint(buf, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn write_str(buf: &mut Vec<u8>, value: &str) {
+    write_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn nanos(time: DateTime<Utc>) -> i64 {
+    // only out of range ~292 years away from the epoch
+    time.timestamp_nanos_opt().unwrap_or_default()
+}
+
+/// Writes the header of a stream.
+pub(crate) fn encode_header(buf: &mut Vec<u8>, metadata: &[(&str, &str)]) {
+    buf.extend_from_slice(MAGIC);
+    buf.push(VERSION);
+
+    write_varint(buf, metadata.len() as u64);
+    for (key, value) in metadata {
+        write_str(buf, key);
+        write_str(buf, value);
+    }
+}
+
+/// The table of static strings of a stream being written.
+#[derive(Debug, Default)]
+pub(crate) struct Encoder {
+    strings: HashMap<&'static str, u64>,
+}
+
+impl Encoder {
+    fn write_static(&mut self, buf: &mut Vec<u8>, value: &'static str) {
+        let next = self.strings.len() as u64;
+        let index = *self.strings.entry(value).or_insert(next);
+
+        write_varint(buf, index);
+        if index == next {
+            write_str(buf, value);
+        }
+    }
+
+    /// Writes a record, including its length.
+    pub(crate) fn encode(&mut self, buf: &mut Vec<u8>, scratch: &mut Vec<u8>, log: &Log) {
+        scratch.clear();
+
+        let time = nanos(log.time);
+        scratch.extend_from_slice(&time.to_le_bytes());
+        write_signed(scratch, nanos(log.last_time).wrapping_sub(time));
+        write_varint(scratch, log.repeat_count.into());
+
+        match log.level {
+            Level::Custom(custom) => {
+                scratch.push(CUSTOM_LEVEL);
+                scratch.push(custom.severity());
+                self.write_static(scratch, custom.name());
+            }
+            level => scratch.push(level.severity()),
+        }
+
+        self.write_static(scratch, log.target);
+        self.write_static(scratch, log.location.file);
+        write_varint(scratch, log.location.line.into());
+        match log
+            .packed()
+            .and_then(|packed| Some((interned::lookup(packed.id)?, packed)))
+        {
+            Some((format, packed)) => {
+                scratch.push(MESSAGE_INTERNED);
+                self.write_static(scratch, format);
+                let args = packed.args.as_bytes();
+                write_varint(scratch, args.len() as u64);
+                scratch.extend_from_slice(args);
+            }
+            None => {
+                scratch.push(MESSAGE_TEXT);
+                write_str(scratch, log.message());
+            }
+        }
+
+        write_varint(scratch, log.fields().len() as u64);
+        for field in log.fields() {
+            self.write_static(scratch, field.name);
+            match &field.value {
+                Value::I64(value) => {
+                    scratch.push(TAG_I64);
+                    write_signed(scratch, *value);
+                }
+                Value::U64(value) => {
+                    scratch.push(TAG_U64);
+                    write_varint(scratch, *value);
+                }
+                Value::F64(value) => {
+                    scratch.push(TAG_F64);
+                    scratch.extend_from_slice(&value.to_le_bytes());
+                }
+                Value::Bool(value) => {
+                    scratch.push(TAG_BOOL);
+                    scratch.push(*value as u8);
+                }
+                Value::Str(value) => {
+                    scratch.push(TAG_STR);
+                    write_str(scratch, value);
+                }
+                Value::Hex(value) => {
+                    scratch.push(TAG_HEX);
+                    write_varint(scratch, (*value).into());
+                }
+            }
+        }
+
+        write_varint(buf, scratch.len() as u64);
+        buf.extend_from_slice(scratch);
+    }
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+/// The message of a record being read.
+enum Message {
+    Text(String),
+    Interned(Packed),
+}
+
+/// A cursor over the bytes of a record.
+struct Record<'a> {
+    bytes: &'a [u8],
+}
+
+impl Record<'_> {
+    fn bytes(&mut self, len: usize) -> io::Result<&[u8]> {
+        if self.bytes.len() < len {
+            return Err(invalid("record is truncated"));
+        }
+
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().expect("length is N"))
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        read_varint(|| self.byte())?.ok_or_else(|| invalid("varint is too long"))
+    }
+
+    fn signed(&mut self) -> io::Result<i64> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn small<T: TryFrom<u64>>(&mut self) -> io::Result<T> {
+        T::try_from(self.varint()?).map_err(|_| invalid("integer is out of range"))
+    }
+
+    fn str(&mut self) -> io::Result<&str> {
+        let len = self.small()?;
+        std::str::from_utf8(self.bytes(len)?).map_err(|_| invalid("string is not UTF-8"))
+    }
+}
+
+/// Reads a varint one byte at a time. Returns `None` if it doesn't fit in an `u64`.
+fn read_varint(mut byte: impl FnMut() -> io::Result<u8>) -> io::Result<Option<u64>> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let b = byte()?;
+        value |= u64::from(b & 0x7F) << shift;
+        if b & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads [`Log`]s from a stream in the [binary format](crate::BinaryWriter), as an iterator.
+///
+/// Names are interned, see [reading logs back](crate::Log#reading-logs-back).
+pub struct BinaryReader<R> {
+    reader: R,
+    version: u8,
+    metadata: Vec<(String, String)>,
+    strings: Vec<&'static str>,
+    buf: Vec<u8>,
+    /// Set after an error, since the stream can't be resynchronized.
+    failed: bool,
+}
+
+impl<R> BinaryReader<R>
+where
+    R: Read,
+{
+    /// Creates a reader, reading the header of the stream. Fails if it isn't a stream in the
+    /// binary format, or if its version isn't supported.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a binary log stream"));
+        }
+
+        let mut version = [0];
+        reader.read_exact(&mut version)?;
+        let [version] = version;
+        if version != VERSION {
+            return Err(invalid("unsupported binary log version"));
+        }
+
+        let mut this = Self {
+            reader,
+            version,
+            metadata: Vec::new(),
+            strings: Vec::new(),
+            buf: Vec::new(),
+            failed: false,
+        };
+
+        let count = this.read_len()?;
+        for _ in 0..count {
+            let key = this.read_string()?;
+            let value = this.read_string()?;
+            this.metadata.push((key, value));
+        }
+
+        Ok(this)
+    }
+
+    /// The version of the format of the stream.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// The metadata in the header of the stream, as key-value pairs.
+    #[inline]
+    pub fn metadata(&self) -> &[(String, String)] {
+        &self.metadata
+    }
+
+    /// Consumes the reader, returning the underlying one.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn read_byte(reader: &mut R) -> io::Result<u8> {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn read_len(&mut self) -> io::Result<usize> {
+        read_varint(|| Self::read_byte(&mut self.reader))?
+            .and_then(|len| usize::try_from(len).ok())
+            .ok_or_else(|| invalid("length is too big"))
+    }
+
+    fn read_string(&mut self) -> io::Result<String> {
+        let len = self.read_len()?;
+        let mut bytes = Vec::new();
+        (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        String::from_utf8(bytes).map_err(|_| invalid("string is not UTF-8"))
+    }
+
+    fn read_static(&mut self, record: &mut Record) -> io::Result<&'static str> {
+        let index = record.small::<usize>()?;
+        match index.cmp(&self.strings.len()) {
+            std::cmp::Ordering::Less => Ok(self.strings[index]),
+            std::cmp::Ordering::Equal => {
+                let value = intern(record.str()?);
+                self.strings.push(value);
+                Ok(value)
+            }
+            std::cmp::Ordering::Greater => Err(invalid("unknown static string")),
+        }
+    }
+
+    /// Reads the next record, or returns `None` at the end of the stream.
+    fn read_log(&mut self) -> io::Result<Option<Log>> {
+        // only a clean end of the stream is allowed before the length
+        let mut first = [0];
+        if self.reader.read(&mut first)? == 0 {
+            return Ok(None);
+        }
+
+        let mut first = Some(first[0]);
+        let reader = &mut self.reader;
+        let len = read_varint(|| match first.take() {
+            Some(byte) => Ok(byte),
+            None => Self::read_byte(reader),
+        })?
+        .and_then(|len| usize::try_from(len).ok())
+        .ok_or_else(|| invalid("length is too big"))?;
+
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.clear();
+        (&mut self.reader).take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let result = self.decode(&mut Record { bytes: &buf });
+        self.buf = buf;
+        result.map(Some)
+    }
+
+    fn decode(&mut self, record: &mut Record) -> io::Result<Log> {
+        let time = i64::from_le_bytes(record.array()?);
+        let last_time = time.wrapping_add(record.signed()?);
+        let repeat_count = record.small()?;
+
+        let level = match record.byte()? {
+            CUSTOM_LEVEL => {
+                let severity = record.byte()?;
+                Level::custom(self.read_static(record)?, severity)
+            }
+            severity => Level::from_severity(severity).ok_or_else(|| invalid("unknown level"))?,
+        };
+
+        let target = self.read_static(record)?;
+        let location = Location {
+            file: self.read_static(record)?,
+            line: record.small()?,
+        };
+        let message = match record.byte()? {
+            MESSAGE_TEXT => Message::Text(record.str()?.to_owned()),
+            MESSAGE_INTERNED => {
+                let format = self.read_static(record)?;
+                let len = record.small()?;
+                Message::Interned(Packed {
+                    id: interned::register(format),
+                    args: PackedArgs::from_bytes(record.bytes(len)?),
+                })
+            }
+            _ => return Err(invalid("unknown message type")),
+        };
+
+        let count = record.small::<usize>()?;
+        // don't trust the count for preallocating more than what the record could hold
+        let mut fields = Vec::with_capacity(count.min(record.bytes.len() / 2));
+        for _ in 0..count {
+            let name = self.read_static(record)?;
+            let value = match record.byte()? {
+                TAG_I64 => Value::I64(record.signed()?),
+                TAG_U64 => Value::U64(record.varint()?),
+                TAG_F64 => Value::F64(f64::from_le_bytes(record.array()?)),
+                TAG_BOOL => Value::Bool(record.byte()? != 0),
+                TAG_STR => Value::Str(record.str()?.to_owned()),
+                TAG_HEX => Value::Hex(record.small()?),
+                _ => return Err(invalid("unknown field type")),
+            };
+
+            fields.push(Field { name, value });
+        }
+
+        let content = match message {
+            Message::Text(message) => Content::new(message, fields),
+            // interned logs have no fields
+            Message::Interned(packed) => Content::packed(packed),
+        };
+
+        let mut log =
+            Log::with_content(Utc.timestamp_nanos(time), level, target, location, content);
+        log.last_time = Utc.timestamp_nanos(last_time);
+        log.repeat_count = repeat_count;
+
+        Ok(log)
+    }
+}
+
+impl<R> Iterator for BinaryReader<R>
+where
+    R: Read,
+{
+    type Item = io::Result<Log>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let result = self.read_log().transpose();
+        self.failed = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{test::test_log, BinaryReader, BinaryWriter, Field, Hex, Level, Log, Sink};
+    use std::io::{self, Write};
+
+    fn logs() -> Vec<Log> {
+        let log = |level, line, message: &str, fields| {
+            let mut log = test_log(message, fields);
+            // down to the nanosecond, to check it's kept
+            log.time += chrono::Duration::nanoseconds(456_789);
+            log.last_time = log.time + chrono::Duration::milliseconds(5);
+            log.repeat_count = 7;
+            log.level = level;
+            log.location.line = line;
+            log
+        };
+
+        vec![
+            log(
+                Level::Trace,
+                10,
+                "fetch",
+                vec![Field::new("pc", Hex(0xBFC0_0000))],
+            ),
+            log(
+                Level::custom("Fatal", 60),
+                20,
+                "halt ✓",
+                vec![
+                    Field::new("a", -3),
+                    Field::new("b", u64::MAX),
+                    Field::new("c", 1.5),
+                    Field::new("d", false),
+                    Field::new("e", "str"),
+                ],
+            ),
+            log(Level::Warn, 30, "", Vec::new()),
+        ]
+    }
+
+    #[test]
+    fn roundtrip() {
+        let logs = logs();
+        let mut writer =
+            BinaryWriter::with_metadata(Vec::new(), &[("program", "psx"), ("build", "1")]).unwrap();
+        for log in &logs {
+            writer.accept(log);
+        }
+        let bytes = writer.into_inner().unwrap();
+
+        let reader = BinaryReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.version(), 1);
+        assert_eq!(
+            reader.metadata(),
+            [
+                ("program".to_owned(), "psx".to_owned()),
+                ("build".to_owned(), "1".to_owned())
+            ]
+        );
+
+        let read = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read.len(), logs.len());
+        for (read, log) in read.iter().zip(&logs) {
+            assert_eq!(read.time, log.time);
+            assert_eq!(read.last_time, log.last_time);
+            assert_eq!(read.repeat_count, log.repeat_count);
+            assert_eq!(read.level, log.level);
+            assert_eq!(read.target, log.target);
+            assert_eq!(read.location, log.location);
+            assert_eq!(read.message(), log.message());
+            assert_eq!(read.fields(), log.fields());
+        }
+    }
+
+    #[test]
+    fn dropping_writes_buffered_logs() {
+        let logs = logs();
+        let mut bytes = Vec::new();
+        let mut writer = BinaryWriter::new(&mut bytes).unwrap();
+        for log in &logs {
+            writer.accept(log);
+        }
+        std::mem::drop(writer);
+
+        let reader = BinaryReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.count(), logs.len());
+    }
+
+    #[test]
+    fn failed_writes_are_retried() {
+        /// Fails once, after taking `fail_after` bytes.
+        struct Flaky {
+            out: Vec<u8>,
+            fail_after: Option<usize>,
+        }
+
+        impl Write for Flaky {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                match self.fail_after {
+                    Some(0) => {
+                        self.fail_after = None;
+                        Err(io::ErrorKind::Other.into())
+                    }
+                    Some(left) => {
+                        let n = left.min(buf.len());
+                        self.out.extend_from_slice(&buf[..n]);
+                        self.fail_after = Some(left - n);
+                        Ok(n)
+                    }
+                    None => {
+                        self.out.extend_from_slice(buf);
+                        Ok(buf.len())
+                    }
+                }
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // fail in the middle of the first log
+        let header = BinaryWriter::new(Vec::new()).unwrap().into_inner().unwrap();
+        let flaky = Flaky {
+            out: Vec::new(),
+            fail_after: Some(header.len() + 3),
+        };
+
+        let logs = logs();
+        let mut writer = BinaryWriter::new(flaky).unwrap();
+        let errors = writer.errors();
+        for log in &logs {
+            writer.accept(log);
+        }
+        writer.flush();
+        assert_eq!(errors.get(), 1);
+        writer.flush();
+        assert_eq!(errors.get(), 1);
+
+        let bytes = writer.into_inner().unwrap().out;
+        let read = BinaryReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read.len(), logs.len());
+        assert_eq!(read[1].message(), logs[1].message());
+    }
+
+    #[test]
+    fn invalid_streams() {
+        assert!(BinaryReader::new(&b"QLOX\x01\x00"[..]).is_err());
+        assert!(BinaryReader::new(&b"QLOG\x02\x00"[..]).is_err());
+
+        let mut writer = BinaryWriter::new(Vec::new()).unwrap();
+        writer.accept(&logs()[0]);
+        let mut bytes = writer.into_inner().unwrap();
+        bytes.pop();
+
+        let mut reader = BinaryReader::new(bytes.as_slice()).unwrap();
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
@@ -0,0 +1,217 @@
+use crate::{sink::Memory, worker::Worker, Level, Logger, Logs, Sink};
+use chrono::{DateTime, Utc};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// What a [`Logger`] does when a log is sent while the channel to its backing thread is full, which
+/// happens when logs are sent faster than the backing thread can process them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backpressure {
+    /// Block until there's room in the channel.
+    #[default]
+    Block,
+    /// Discard the log being sent.
+    DropNewest,
+    /// Discard the oldest log waiting in the channel to make room for the one being sent.
+    DropOldest,
+    /// Block until there's room in the channel, but for at most the given duration. If it's still
+    /// full after that, discard the log being sent.
+    Timeout(Duration),
+}
+
+/// A function returning the current time, used to timestamp logs.
+pub(crate) type TimeSource = Arc<dyn Fn() -> DateTime<Utc> + Send + Sync>;
+
+/// A builder for configuring a [`Logger`]. Created by [`Logger::builder`].
+pub struct LoggerBuilder {
+    pub(crate) limit: Option<usize>,
+    pub(crate) memory_budget: Option<usize>,
+    pub(crate) capacity: Option<usize>,
+    pub(crate) channel_capacity: usize,
+    pub(crate) backpressure: Backpressure,
+    pub(crate) thread_name: Option<String>,
+    pub(crate) min_level: Level,
+    pub(crate) time_source: TimeSource,
+    pub(crate) dedupe: bool,
+    pub(crate) deferred_formatting: bool,
+    pub(crate) sinks: Vec<Box<dyn Sink>>,
+}
+
+impl Default for LoggerBuilder {
+    fn default() -> Self {
+        Self {
+            limit: None,
+            memory_budget: None,
+            capacity: None,
+            channel_capacity: u16::MAX as usize,
+            backpressure: Backpressure::default(),
+            thread_name: None,
+            min_level: Level::Trace,
+            time_source: Arc::new(Utc::now),
+            dedupe: false,
+            deferred_formatting: false,
+            sinks: Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Debug for LoggerBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoggerBuilder")
+            .field("limit", &self.limit)
+            .field("memory_budget", &self.memory_budget)
+            .field("capacity", &self.capacity)
+            .field("channel_capacity", &self.channel_capacity)
+            .field("backpressure", &self.backpressure)
+            .field("thread_name", &self.thread_name)
+            .field("min_level", &self.min_level)
+            .field("dedupe", &self.dedupe)
+            .field("deferred_formatting", &self.deferred_formatting)
+            .field("sinks", &self.sinks.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl LoggerBuilder {
+    /// Sets the maximum amount of logs kept by the logger. When the limit is exceeded, the oldest
+    /// logs are deleted when logging new things.
+    ///
+    /// By default there's no limit. *You should* give the logger a limit or a
+    /// [memory budget](LoggerBuilder::memory_budget), since a limitless logger will only grow in
+    /// memory usage unless you call [`Logger::clear`]. A limit of zero disables
+    /// the in-memory history, which is useful if logs only go to other [`Sink`]s.
+    #[inline]
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Sets the maximum amount of memory, in bytes, taken by the logs kept by the logger. When the
+    /// budget is exceeded, the oldest logs are deleted when logging new things, and logs which
+    /// don't fit in it on their own aren't kept at all. This can be combined with
+    /// [`limit`](LoggerBuilder::limit), in which case both apply.
+    ///
+    /// What counts is the memory taken by each log's message and fields, plus a fixed overhead per
+    /// log. Logs which are only formatted when read, such as with
+    /// [deferred formatting](LoggerBuilder::deferred_formatting), are counted without their
+    /// message and fields until they're read. Since that happens outside of the backing thread,
+    /// reading them can take the usage over the budget until the next log is stored, which evicts
+    /// the oldest logs as usual. See [`Logger::memory_usage`].
+    ///
+    /// Spare capacity isn't counted: the storage logs are kept in grows by doubling and only
+    /// shrinks when the logger is [cleared](Logger::clear), so the memory actually allocated can
+    /// reach about twice the budget.
+    #[inline]
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Sets how many logs the logger has room for upfront. Defaults to the limit, if any.
+    #[inline]
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Sets how many logs can be waiting to be processed by the backing thread before the
+    /// [`Backpressure`] policy kicks in. Defaults to [`u16::MAX`].
+    #[inline]
+    pub fn channel_capacity(mut self, channel_capacity: usize) -> Self {
+        self.channel_capacity = channel_capacity;
+        self
+    }
+
+    /// Sets the [`Backpressure`] policy of the logger. Defaults to [`Backpressure::Block`].
+    #[inline]
+    pub fn backpressure(mut self, backpressure: Backpressure) -> Self {
+        self.backpressure = backpressure;
+        self
+    }
+
+    /// Sets the name of the backing thread. Unnamed by default.
+    #[inline]
+    pub fn thread_name(mut self, thread_name: impl Into<String>) -> Self {
+        self.thread_name = Some(thread_name.into());
+        self
+    }
+
+    /// Sets the minimum [`Level`] of the logs this logger accepts. Logs with a lower level are
+    /// discarded before being sent to the backing thread. Defaults to [`Level::Trace`].
+    ///
+    /// This can be changed later with [`Logger::set_min_level`].
+    #[inline]
+    pub fn min_level(mut self, min_level: Level) -> Self {
+        self.min_level = min_level;
+        self
+    }
+
+    /// Sets the function used to timestamp logs when they're processed by the backing thread.
+    /// Defaults to [`Utc::now`].
+    #[inline]
+    pub fn time_source<F>(mut self, time_source: F) -> Self
+    where
+        F: Fn() -> DateTime<Utc> + Send + Sync + 'static,
+    {
+        self.time_source = Arc::new(time_source);
+        self
+    }
+
+    /// Sets whether consecutive repeated logs are collapsed into one in the in-memory history of
+    /// the logger (other sinks see every log). When enabled, a log identical to the last one kept
+    /// (same level, call site, message and fields) isn't stored: instead, the
+    /// [`Log::repeat_count`](crate::Log::repeat_count) of the last one is incremented and its
+    /// [`Log::last_time`](crate::Log::last_time) updated. Disabled by default.
+    #[inline]
+    pub fn dedupe(mut self, dedupe: bool) -> Self {
+        self.dedupe = dedupe;
+        self
+    }
+
+    /// Sets whether logs are formatted lazily. When enabled, the backing thread doesn't format the
+    /// message and fields of logs: instead, logs keep their [`Loggable`](crate::Loggable) around
+    /// and format it the first time [`Log::message`](crate::Log::message) or
+    /// [`Log::fields`](crate::Log::fields) is called. Logs evicted from the in-memory history
+    /// before being read are never formatted. Disabled by default.
+    ///
+    /// This only pays off if logs are mostly left unread, as any [`Sink`] which reads logs or
+    /// [`dedupe`](LoggerBuilder::dedupe) formats them on the backing thread anyway. Note that
+    /// anything captured by a loggable lives as long as its log.
+    ///
+    /// The [`Display`](std::fmt::Display) code of deferred values then runs on the thread reading
+    /// the logs, while it holds the lock on the in-memory history (in
+    /// [`Logger::with_logs`](crate::Logger::with_logs) or `Logger::export_jsonl`). It must not
+    /// call [`Logger::flush`](crate::Logger::flush), `with_logs` or any other method of the same
+    /// logger which needs that lock, as that deadlocks. If it panics, the message of the log is
+    /// replaced with [`FORMAT_ERROR`](crate::FORMAT_ERROR).
+    #[inline]
+    pub fn deferred_formatting(mut self, deferred_formatting: bool) -> Self {
+        self.deferred_formatting = deferred_formatting;
+        self
+    }
+
+    /// Adds a [`Sink`] to the logger, which will be handed every processed log. Sinks are handed
+    /// logs in the order they were added, and always before the in-memory history of the logger.
+    #[inline]
+    pub fn sink(mut self, sink: impl Sink + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Builds the [`Logger`], spawning its backing thread.
+    pub fn build(self) -> Logger {
+        let capacity = self.capacity.or(self.limit).unwrap_or(0);
+        let logs = Arc::new(Mutex::new(Logs::with_capacity(capacity)));
+        let memory = Memory {
+            logs: logs.clone(),
+            limit: self.limit,
+            budget: self.memory_budget,
+            dedupe: self.dedupe,
+        };
+        let worker = Arc::new(Worker::spawn(memory, self));
+
+        Logger { logs, worker }
+    }
+}
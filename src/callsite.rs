@@ -0,0 +1,44 @@
+/// A location in the source code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Location {
+    pub file: &'static str,
+    pub line: u32,
+}
+
+impl std::fmt::Display for Location {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// Static information about a place in the source code which emits logs. The logging macros
+/// create one for each of their call sites, and you can do the same with
+/// [`callsite!`](crate::callsite!).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Callsite {
+    /// The module path of the call site.
+    pub target: &'static str,
+    pub location: Location,
+}
+
+impl Callsite {
+    #[inline]
+    pub const fn new(target: &'static str, file: &'static str, line: u32) -> Self {
+        Self {
+            target,
+            location: Location { file, line },
+        }
+    }
+}
+
+/// Expands to a `&'static` [`Callsite`] describing the place it's invoked at.
+#[macro_export]
+macro_rules! callsite {
+    () => {{
+        static CALLSITE: $crate::Callsite =
+            $crate::Callsite::new(::std::module_path!(), ::std::file!(), ::std::line!());
+        &CALLSITE
+    }};
+}
@@ -0,0 +1,315 @@
+use crate::{
+    interned::Packed, loggable::ErasedLoggable, logs::formatted_size, Field, Loggable, FORMAT_ERROR,
+};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+#[derive(Debug)]
+struct Formatted {
+    message: String,
+    fields: Vec<Field>,
+}
+
+/// A message owned by the arena of [`Logs`](crate::Logs) rather than by its [`Content`].
+#[derive(Clone, Copy)]
+struct StoredStr {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: this is a `&str` whose lifetime is managed by `Logs`, which only hands out contents
+// pointing into its arena behind a shared borrow of itself
+unsafe impl Send for StoredStr {}
+unsafe impl Sync for StoredStr {}
+
+impl StoredStr {
+    #[inline]
+    fn new(message: &str) -> Self {
+        Self {
+            ptr: NonNull::from(message.as_bytes()).cast(),
+            len: message.len(),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The string this was created from has to still be there.
+    #[inline]
+    unsafe fn as_str(&self) -> &str {
+        let bytes = std::slice::from_raw_parts(self.ptr.as_ptr(), self.len);
+        std::str::from_utf8_unchecked(bytes)
+    }
+}
+
+/// What the message of a [`Content`] is formatted from.
+enum Source {
+    /// Nothing, it was formatted upfront.
+    None,
+    /// Nothing, it was formatted upfront and its message was moved to the arena of
+    /// [`Logs`](crate::Logs).
+    Stored {
+        message: StoredStr,
+        fields: Vec<Field>,
+    },
+    /// A loggable, until it's formatted.
+    Loggable(Mutex<Option<ErasedLoggable>>),
+    /// An interned format string and its arguments, which are kept around after formatting.
+    Packed(Packed),
+}
+
+/// The message and fields of a [`Log`](crate::Log). They're either formatted by the backing
+/// thread or, with [deferred formatting](crate::LoggerBuilder::deferred_formatting) and for
+/// interned logs, the first time they're read.
+pub(crate) struct Content {
+    formatted: OnceLock<Formatted>,
+    source: Source,
+    /// Where the size of the message and fields is added if they're formatted when first read.
+    /// See [`Content::charge_to`].
+    charge: Option<Arc<AtomicUsize>>,
+}
+
+impl Content {
+    #[inline]
+    pub fn new(message: String, fields: Vec<Field>) -> Self {
+        Self {
+            formatted: OnceLock::from(Formatted { message, fields }),
+            source: Source::None,
+            charge: None,
+        }
+    }
+
+    /// Formats `loggable` right away.
+    #[inline]
+    pub fn format(loggable: ErasedLoggable) -> Self {
+        let Formatted { message, fields } = format(loggable);
+        Self::new(message, fields)
+    }
+
+    /// Keeps `loggable` around to format it when first read.
+    #[inline]
+    pub fn deferred(loggable: ErasedLoggable) -> Self {
+        Self {
+            formatted: OnceLock::new(),
+            source: Source::Loggable(Mutex::new(Some(loggable))),
+            charge: None,
+        }
+    }
+
+    /// Keeps an interned log around to format it when first read.
+    #[inline]
+    pub fn packed(packed: Packed) -> Self {
+        Self {
+            formatted: OnceLock::new(),
+            source: Source::Packed(packed),
+            charge: None,
+        }
+    }
+
+    /// Refers to `message` instead of owning it, for logs stored in [`Logs`](crate::Logs).
+    ///
+    /// # Safety
+    ///
+    /// `message` has to stay where it is for as long as this content exists, unless the content
+    /// is pointed to its new place with [`Content::move_stored`] before being read.
+    #[inline]
+    pub unsafe fn stored(message: &str, fields: Vec<Field>) -> Self {
+        Self {
+            formatted: OnceLock::new(),
+            source: Source::Stored {
+                message: StoredStr::new(message),
+                fields,
+            },
+            charge: None,
+        }
+    }
+
+    /// Points content created with [`Content::stored`] to `message`, where its message was moved.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Content::stored`].
+    #[inline]
+    pub unsafe fn move_stored(&mut self, message: &str) {
+        if let Source::Stored {
+            message: stored, ..
+        } = &mut self.source
+        {
+            debug_assert_eq!(stored.as_str(), message);
+            *stored = StoredStr::new(message);
+        }
+    }
+
+    /// The fields of content created with [`Content::stored`], so that their memory can be
+    /// reused.
+    #[inline]
+    pub fn into_stored_fields(self) -> Option<Vec<Field>> {
+        match self.source {
+            Source::Stored { fields, .. } => Some(fields),
+            _ => None,
+        }
+    }
+
+    /// Adds the size of the message and fields to `counter` when they're formatted, if they aren't
+    /// yet. This is how [`Logs`](crate::Logs) accounts for logs formatted once they're stored.
+    #[inline]
+    pub fn charge_to(&mut self, counter: Arc<AtomicUsize>) {
+        if self.formatted.get().is_none() {
+            self.charge = Some(counter);
+        }
+    }
+
+    /// What was added to the counter given to [`Content::charge_to`], if anything.
+    #[inline]
+    pub fn charged(&self) -> usize {
+        match &self.charge {
+            Some(_) => self.formatted_size().unwrap_or(0),
+            None => 0,
+        }
+    }
+
+    /// How much memory the message and fields take, in bytes, if they're formatted.
+    #[inline]
+    pub fn formatted_size(&self) -> Option<usize> {
+        match &self.source {
+            Source::Stored { .. } => Some(formatted_size(self.message(), self.fields())),
+            _ => self
+                .formatted
+                .get()
+                .map(|f| formatted_size(&f.message, &f.fields)),
+        }
+    }
+
+    /// Formats `loggable` right away, reusing the memory of this content if it was formatted
+    /// upfront too.
+    pub fn reformat(&mut self, loggable: ErasedLoggable) {
+        let formatted = match (&self.source, self.formatted.get_mut()) {
+            (Source::None, Some(formatted)) => formatted,
+            _ => return *self = Self::format(loggable),
+        };
+
+        formatted.message.clear();
+        formatted.fields.clear();
+        if loggable
+            .log_with_fields(&mut formatted.message, &mut formatted.fields)
+            .is_err()
+        {
+            formatted.message.push_str(FORMAT_ERROR);
+        }
+    }
+
+    /// Whether this is formatted when first read rather than upfront.
+    #[inline]
+    pub fn is_lazy(&self) -> bool {
+        matches!(self.source, Source::Loggable(_) | Source::Packed(_))
+    }
+
+    /// The interned log this was created from, if any.
+    #[inline]
+    pub fn as_packed(&self) -> Option<&Packed> {
+        match &self.source {
+            Source::Packed(packed) => Some(packed),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn get(&self) -> &Formatted {
+        self.formatted.get_or_init(|| {
+            let formatted = match &self.source {
+                Source::None | Source::Stored { .. } => {
+                    unreachable!("content without a source is formatted")
+                }
+                Source::Loggable(loggable) => {
+                    let loggable = loggable
+                        .lock()
+                        .expect("lock is not poisoned")
+                        .take()
+                        .expect("unformatted content has a loggable");
+
+                    // this runs while the in-memory history is locked, so a panic would poison it
+                    // for good
+                    panic::catch_unwind(AssertUnwindSafe(|| format(loggable))).unwrap_or_else(
+                        |_| Formatted {
+                            message: FORMAT_ERROR.to_owned(),
+                            fields: Vec::new(),
+                        },
+                    )
+                }
+                Source::Packed(packed) => {
+                    let mut message = String::new();
+                    if packed.decode(&mut message).is_err() {
+                        message.push_str(FORMAT_ERROR);
+                    }
+
+                    Formatted {
+                        message,
+                        fields: Vec::new(),
+                    }
+                }
+            };
+
+            if let Some(charge) = &self.charge {
+                let size = formatted_size(&formatted.message, &formatted.fields);
+                charge.fetch_add(size, Ordering::Relaxed);
+            }
+
+            formatted
+        })
+    }
+
+    #[inline]
+    pub fn message(&self) -> &str {
+        match &self.source {
+            // SAFETY: `Content::stored` requires the message to be kept where it points to
+            Source::Stored { message, .. } => unsafe { message.as_str() },
+            _ => &self.get().message,
+        }
+    }
+
+    #[inline]
+    pub fn fields(&self) -> &[Field] {
+        match &self.source {
+            Source::Stored { fields, .. } => fields,
+            _ => &self.get().fields,
+        }
+    }
+}
+
+fn format(loggable: ErasedLoggable) -> Formatted {
+    let mut message = String::new();
+    let mut fields = Vec::new();
+    if loggable.log_with_fields(&mut message, &mut fields).is_err() {
+        message.push_str(FORMAT_ERROR);
+    }
+    message.shrink_to_fit();
+
+    Formatted { message, fields }
+}
+
+impl Clone for Content {
+    /// Formats the message if it hasn't been yet, since loggables can't be cloned. A stored
+    /// message is copied, so the clone doesn't depend on [`Logs`](crate::Logs).
+    #[inline]
+    fn clone(&self) -> Self {
+        if let Source::Packed(packed) = &self.source {
+            return Self::packed(packed.clone());
+        }
+
+        Self::new(self.message().to_owned(), self.fields().to_vec())
+    }
+}
+
+impl std::fmt::Debug for Content {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Content")
+            .field("message", &self.message())
+            .field("fields", &self.fields())
+            .finish()
+    }
+}
@@ -0,0 +1,54 @@
+/// The error returned by [`Logger::try_log`](crate::Logger::try_log) when a log could not be
+/// sent to the backing thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogError {
+    /// The channel to the backing thread is full, because logs are being sent faster than it can
+    /// process them.
+    QueueFull,
+    /// The backing thread is gone, either because the logger was
+    /// [shut down](crate::Logger::shutdown) or because it panicked.
+    WorkerDead,
+}
+
+impl std::fmt::Display for LogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogError::QueueFull => write!(f, "the log queue is full"),
+            LogError::WorkerDead => write!(f, "the logger's backing thread is gone"),
+        }
+    }
+}
+
+impl std::error::Error for LogError {}
+
+/// The error returned by [`Format::parse`](crate::Format::parse) when a template is invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFormatError {
+    position: usize,
+    reason: &'static str,
+}
+
+impl ParseFormatError {
+    #[inline]
+    pub(crate) fn new(position: usize, reason: &'static str) -> Self {
+        Self { position, reason }
+    }
+
+    /// The byte offset in the template where the error was found.
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl std::fmt::Display for ParseFormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at byte {} of format template",
+            self.reason, self.position
+        )
+    }
+}
+
+impl std::error::Error for ParseFormatError {}
@@ -0,0 +1,164 @@
+/// A structured key-value pair attached to a [`Log`](crate::Log).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Field {
+    pub name: &'static str,
+    pub value: Value,
+}
+
+impl Field {
+    #[inline]
+    pub fn new(name: &'static str, value: impl Into<Value>) -> Self {
+        Self {
+            name,
+            value: value.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Field {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+/// Wraps an `u32` so that it's recorded as a [`Value::Hex`] field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Hex(pub u32);
+
+impl std::fmt::Display for Hex {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0x{:08X}", self.0)
+    }
+}
+
+/// The value of a [`Field`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Value {
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Bool(bool),
+    Str(String),
+    /// An `u32` which is displayed in hexadecimal, such as an address or a register.
+    Hex(u32),
+}
+
+impl Value {
+    /// This value as an `i64`, if it's an integer which fits in one.
+    #[inline]
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Self::I64(value) => Some(value),
+            Self::U64(value) => value.try_into().ok(),
+            Self::Hex(value) => Some(value.into()),
+            _ => None,
+        }
+    }
+
+    /// This value as an `u64`, if it's an integer which fits in one.
+    #[inline]
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Self::I64(value) => value.try_into().ok(),
+            Self::U64(value) => Some(value),
+            Self::Hex(value) => Some(value.into()),
+            _ => None,
+        }
+    }
+
+    /// This value as an `f64`, if it's a number.
+    #[inline]
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Self::I64(value) => Some(value as f64),
+            Self::U64(value) => Some(value as f64),
+            Self::F64(value) => Some(value),
+            Self::Hex(value) => Some(value.into()),
+            _ => None,
+        }
+    }
+
+    /// This value as a `bool`, if it's one.
+    #[inline]
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Self::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// This value as a string, if it's one.
+    #[inline]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::I64(value) => write!(f, "{value}"),
+            Self::U64(value) => write!(f, "{value}"),
+            Self::F64(value) => write!(f, "{value}"),
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::Str(value) => write!(f, "{value}"),
+            Self::Hex(value) => write!(f, "{}", Hex(*value)),
+        }
+    }
+}
+
+macro_rules! impl_from {
+    ($variant:ident: $($ty:ty),*) => {
+        $(
+            impl From<$ty> for Value {
+                #[inline]
+                fn from(value: $ty) -> Self {
+                    Self::$variant(value as _)
+                }
+            }
+        )*
+    };
+}
+
+impl_from!(I64: i8, i16, i32, i64, isize);
+impl_from!(U64: u8, u16, u32, u64, usize);
+impl_from!(F64: f32, f64);
+
+impl From<bool> for Value {
+    #[inline]
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<&str> for Value {
+    #[inline]
+    fn from(value: &str) -> Self {
+        Self::Str(value.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    #[inline]
+    fn from(value: String) -> Self {
+        Self::Str(value)
+    }
+}
+
+impl From<Hex> for Value {
+    #[inline]
+    fn from(value: Hex) -> Self {
+        Self::Hex(value.0)
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{Log, ParseFormatError};
+use chrono::format::{Item, StrftimeItems};
+use std::{
+    fmt::{self, Display, Write},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+/// The template used by [`Format::default`], which is also how [`Log`]s are displayed.
+const DEFAULT: &str =
+    "{time:%Y-%m-%d %H:%M:%S%.3f} {level:>5} [{target}] {message}{fields}{repeats}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// How a placeholder is padded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Padding {
+    align: Align,
+    width: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Piece {
+    Literal(String),
+    Time(Vec<Item<'static>>),
+    Level(Option<Padding>),
+    Target(Option<Padding>),
+    File(Option<Padding>),
+    Line(Option<Padding>),
+    Location(Option<Padding>),
+    Message,
+    Fields,
+    Repeats,
+}
+
+/// A compiled line format for [`Log`]s, parsed from a template such as
+/// `"{time:%H:%M:%S%.3f} {level:>5} [{target}] {message}"`. Use it with [`Log::display_with`].
+///
+/// Templates are made of literal text and placeholders between braces, which are replaced by a
+/// part of the log:
+///
+/// | Placeholder      | Replaced by                                                            |
+/// |------------------|------------------------------------------------------------------------|
+/// | `{time}`         | [`Log::time`], formatted as `%Y-%m-%d %H:%M:%S%.3f`                    |
+/// | `{time:<fmt>}`   | [`Log::time`], formatted with the given [`strftime`] specification     |
+/// | `{level}`        | [`Log::level`]                                                         |
+/// | `{target}`       | [`Log::target`]                                                        |
+/// | `{file}`         | the file of [`Log::location`]                                          |
+/// | `{line}`         | the line of [`Log::location`]                                          |
+/// | `{location}`     | [`Log::location`], as `file:line`                                      |
+/// | `{message}`      | [`Log::message`]                                                       |
+/// | `{fields}`       | every one of [`Log::fields`] as `name=value`, each preceded by a space |
+/// | `{repeats}`      | ` (repeated N times)` if [`Log::repeat_count`] isn't zero              |
+///
+/// `{level}`, `{target}`, `{file}`, `{line}` and `{location}` can be padded to a minimum width
+/// like in [`std::fmt`], e.g. `{level:>5}` or `{target:<20}`. Literal braces are written as `{{`
+/// and `}}`.
+///
+/// [`strftime`]: chrono::format::strftime
+#[derive(Debug, Clone)]
+pub struct Format {
+    pieces: Vec<Piece>,
+}
+
+impl Format {
+    /// Parses a format template.
+    pub fn parse(template: &str) -> Result<Self, ParseFormatError> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.char_indices().peekable();
+
+        while let Some((position, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|&(_, c)| c == '{').is_some() => literal.push('{'),
+                '}' if chars.next_if(|&(_, c)| c == '}').is_some() => literal.push('}'),
+                '}' => return Err(ParseFormatError::new(position, "unmatched `}`")),
+                '{' => {
+                    let start = position + 1;
+                    let Some(len) = template[start..].find('}') else {
+                        return Err(ParseFormatError::new(position, "unclosed placeholder"));
+                    };
+
+                    let placeholder = &template[start..start + len];
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(Piece::parse(placeholder, start)?);
+
+                    while chars.next_if(|&(i, _)| i <= start + len).is_some() {}
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+
+        Ok(Self { pieces })
+    }
+
+    /// The default format, used when displaying a [`Log`]. Equivalent to the template
+    /// `"{time:%Y-%m-%d %H:%M:%S%.3f} {level:>5} [{target}] {message}{fields}{repeats}"`.
+    pub(crate) fn default_ref() -> &'static Self {
+        static FORMAT: OnceLock<Format> = OnceLock::new();
+        FORMAT.get_or_init(|| Format::parse(DEFAULT).expect("default format is valid"))
+    }
+
+    /// Writes `log` in this format.
+    pub(crate) fn write(&self, f: &mut fmt::Formatter<'_>, log: &Log) -> fmt::Result {
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(literal) => f.write_str(literal)?,
+                Piece::Time(items) => {
+                    write!(f, "{}", log.time.format_with_items(items.iter()))?;
+                }
+                Piece::Level(padding) => pad(f, *padding, &log.level)?,
+                Piece::Target(padding) => pad(f, *padding, &log.target)?,
+                Piece::File(padding) => pad(f, *padding, &log.location.file)?,
+                Piece::Line(padding) => pad(f, *padding, &log.location.line)?,
+                Piece::Location(padding) => match padding {
+                    // `Location` doesn't pad itself
+                    Some(_) => pad(f, *padding, &log.location.to_string())?,
+                    None => write!(f, "{}", log.location)?,
+                },
+                Piece::Message => f.write_str(log.message())?,
+                Piece::Fields => {
+                    for field in log.fields() {
+                        write!(f, " {field}")?;
+                    }
+                }
+                Piece::Repeats => {
+                    if log.repeat_count > 0 {
+                        write!(f, " (repeated {} times)", log.repeat_count)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Format {
+    #[inline]
+    fn default() -> Self {
+        Self::default_ref().clone()
+    }
+}
+
+impl FromStr for Format {
+    type Err = ParseFormatError;
+
+    #[inline]
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        Self::parse(template)
+    }
+}
+
+impl Piece {
+    /// Parses the contents of a placeholder, which starts at byte `position` of the template.
+    fn parse(placeholder: &str, position: usize) -> Result<Self, ParseFormatError> {
+        let (name, spec) = match placeholder.split_once(':') {
+            Some((name, spec)) => (name, Some(spec)),
+            None => (placeholder, None),
+        };
+
+        let padding = || match spec {
+            Some(spec) => Padding::parse(spec)
+                .map(Some)
+                .ok_or_else(|| ParseFormatError::new(position, "invalid padding")),
+            None => Ok(None),
+        };
+        let no_spec = |piece| match spec {
+            Some(_) => Err(ParseFormatError::new(
+                position,
+                "placeholder doesn't take a specification",
+            )),
+            None => Ok(piece),
+        };
+
+        match name {
+            "time" => {
+                let spec = spec.unwrap_or("%Y-%m-%d %H:%M:%S%.3f");
+                StrftimeItems::new(spec)
+                    .parse_to_owned()
+                    .map(Piece::Time)
+                    .map_err(|_| ParseFormatError::new(position, "invalid time format"))
+            }
+            "level" => padding().map(Piece::Level),
+            "target" => padding().map(Piece::Target),
+            "file" => padding().map(Piece::File),
+            "line" => padding().map(Piece::Line),
+            "location" => padding().map(Piece::Location),
+            "message" => no_spec(Piece::Message),
+            "fields" => no_spec(Piece::Fields),
+            "repeats" => no_spec(Piece::Repeats),
+            _ => Err(ParseFormatError::new(position, "unknown placeholder")),
+        }
+    }
+}
+
+impl Padding {
+    /// Parses an `[<^>]width` specification. Without an alignment, values are left-aligned.
+    fn parse(spec: &str) -> Option<Self> {
+        let (align, width) = match spec.as_bytes().first()? {
+            b'<' => (Align::Left, &spec[1..]),
+            b'^' => (Align::Center, &spec[1..]),
+            b'>' => (Align::Right, &spec[1..]),
+            _ => (Align::Left, spec),
+        };
+
+        let width = width.parse().ok()?;
+        Some(Self { align, width })
+    }
+}
+
+fn pad(f: &mut fmt::Formatter<'_>, padding: Option<Padding>, value: &dyn Display) -> fmt::Result {
+    let Some(Padding { align, width }) = padding else {
+        return write!(f, "{value}");
+    };
+
+    match align {
+        Align::Left => write!(f, "{value:<width$}"),
+        Align::Center => write!(f, "{value:^width$}"),
+        Align::Right => write!(f, "{value:>width$}"),
+    }
+}
+
+/// A [`Log`] displayed with a [`Format`]. Created by [`Log::display_with`].
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayWith<'a> {
+    pub(crate) log: &'a Log,
+    pub(crate) format: &'a Format,
+}
+
+impl Display for DisplayWith<'_> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.format.write(f, self.log)
+    }
+}
+
+/// Writes `log` in `format` as a line into `buf`, clearing it first.
+pub(crate) fn write_line(buf: &mut String, log: &Log, format: &Format) {
+    buf.clear();
+    writeln!(buf, "{}", log.display_with(format)).expect("formatting a log never fails");
+}
+
+#[cfg(test)]
+mod test {
+    use super::Format;
+    use crate::{test::test_log, Field};
+
+    #[test]
+    fn templates() {
+        let mut log = test_log("unhandled DMA channel", vec![Field::new("channel", 2)]);
+        log.repeat_count = 2;
+        let render = |template| {
+            log.display_with(&Format::parse(template).unwrap())
+                .to_string()
+        };
+
+        assert_eq!(
+            log.to_string(),
+            "2024-03-01 12:34:56.789  Warn [emu::cpu] unhandled DMA channel channel=2 (repeated 2 times)"
+        );
+        assert_eq!(
+            render("{time:%H:%M:%S%.3f} {level:<5}|{location}: {message}"),
+            "12:34:56.789 Warn |src/cpu.rs:42: unhandled DMA channel"
+        );
+        assert_eq!(
+            render("{{{file:>12}}} {line:^6}|{target:3}{fields}"),
+            "{  src/cpu.rs}   42  |emu::cpu channel=2"
+        );
+        assert_eq!(render("no placeholders"), "no placeholders");
+    }
+
+    #[test]
+    fn invalid_templates() {
+        for (template, position) in [
+            ("{message", 0),
+            ("a } b", 2),
+            ("{nope}", 1),
+            ("x {message:>5}", 3),
+            ("{level:>x}", 1),
+            ("{time:%Q}", 1),
+        ] {
+            let err = Format::parse(template).unwrap_err();
+            assert_eq!(err.position(), position, "{template}: {err}");
+        }
+    }
+}
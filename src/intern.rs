@@ -0,0 +1,24 @@
+use std::{
+    collections::HashSet,
+    sync::{Mutex, OnceLock},
+};
+
+/// Returns a `&'static` copy of `s`, leaking it the first time it's seen. See
+/// [reading logs back](crate::Log#reading-logs-back).
+pub(crate) fn intern(s: &str) -> &'static str {
+    static STRINGS: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+
+    let mut strings = STRINGS
+        .get_or_init(Default::default)
+        .lock()
+        .expect("lock is not poisoned");
+
+    match strings.get(s) {
+        Some(interned) => interned,
+        None => {
+            let interned: &'static str = Box::leak(s.into());
+            strings.insert(interned);
+            interned
+        }
+    }
+}
@@ -0,0 +1,873 @@
+//! Interned format strings with binary-encoded arguments, used by
+//! [`log_interned!`](crate::log_interned!) and friends.
+//!
+//! Each call site registers its format string once in a global table, and logs only carry its id
+//! and their arguments encoded as bytes, which are turned into text when the message is read.
+//!
+//! Arguments are encoded as a tag byte followed by their value: unsigned and signed integers as
+//! LEB128 varints (zigzag encoded if signed), floats as their little-endian bytes, booleans as a
+//! byte, chars as a varint and strings as their length followed by their UTF-8 bytes.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+#[derive(Default)]
+struct Registry {
+    /// Format strings, indexed by their id minus one.
+    formats: Vec<&'static str>,
+    ids: HashMap<&'static str, u32>,
+}
+
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Registers a format string, returning its id. Registering the same string twice returns the same
+/// id.
+pub(crate) fn register(format: &'static str) -> u32 {
+    let mut registry = registry().lock().expect("lock is not poisoned");
+    if let Some(&id) = registry.ids.get(format) {
+        return id;
+    }
+
+    registry.formats.push(format);
+    let id = u32::try_from(registry.formats.len()).expect("less than u32::MAX format strings");
+    registry.ids.insert(format, id);
+
+    id
+}
+
+/// The format string with the given id, if it has been registered.
+pub(crate) fn lookup(id: u32) -> Option<&'static str> {
+    let registry = registry().lock().expect("lock is not poisoned");
+    let index = usize::try_from(id).ok()?.checked_sub(1)?;
+    registry.formats.get(index).copied()
+}
+
+/// A format string which is registered in a global table the first time it's used. The interned
+/// logging macros create one for each of their call sites.
+#[derive(Debug)]
+pub struct InternedFormat {
+    format: &'static str,
+    /// Zero until registered.
+    id: AtomicU32,
+}
+
+impl InternedFormat {
+    /// # Panics
+    ///
+    /// If `format` uses anything interned logs can't format, e.g. named or explicitly positioned
+    /// arguments or a formatting trait other than `?`, `x`, `X`, `b` and `o`. In a `static`, as
+    /// the interned logging macros declare them, this is a compile-time error.
+    #[inline]
+    pub const fn new(format: &'static str) -> Self {
+        assert!(
+            is_supported(format),
+            "unsupported interned format string: only implicitly positioned arguments (`{{}}`, \
+             `{{:x}}`, ...) with the `?`, `x`, `X`, `b` and `o` formatting traits are supported"
+        );
+
+        Self {
+            format,
+            id: AtomicU32::new(0),
+        }
+    }
+
+    /// The format string.
+    #[inline]
+    pub fn format(&self) -> &'static str {
+        self.format
+    }
+
+    /// The id of the format string, registering it if it hasn't been yet.
+    #[inline]
+    pub fn id(&self) -> u32 {
+        match self.id.load(Ordering::Relaxed) {
+            0 => {
+                let id = register(self.format);
+                self.id.store(id, Ordering::Relaxed);
+                id
+            }
+            id => id,
+        }
+    }
+}
+
+const TAG_U8: u8 = 0;
+const TAG_U16: u8 = 1;
+const TAG_U32: u8 = 2;
+const TAG_U64: u8 = 3;
+const TAG_I8: u8 = 4;
+const TAG_I16: u8 = 5;
+const TAG_I32: u8 = 6;
+const TAG_I64: u8 = 7;
+const TAG_F32: u8 = 8;
+const TAG_F64: u8 = 9;
+const TAG_BOOL: u8 = 10;
+const TAG_CHAR: u8 = 11;
+const TAG_STR: u8 = 12;
+
+/// How many bytes of arguments are stored inline, without allocating.
+const INLINE_ARGS: usize = 22;
+
+/// The encoded arguments of an interned log.
+#[derive(Debug, Clone)]
+pub(crate) enum PackedArgs {
+    Inline { len: u8, bytes: [u8; INLINE_ARGS] },
+    Heap(Box<[u8]>),
+}
+
+impl PackedArgs {
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Inline { len, bytes } => &bytes[..usize::from(*len)],
+            Self::Heap(bytes) => bytes,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut encoder = ArgEncoder::new();
+        encoder.extend(bytes);
+        encoder.finish()
+    }
+}
+
+/// An interned log: the id of its format string and its encoded arguments.
+#[derive(Debug, Clone)]
+pub(crate) struct Packed {
+    pub id: u32,
+    pub args: PackedArgs,
+}
+
+impl Packed {
+    /// Formats the message of this log.
+    pub fn decode(&self, writer: &mut dyn Write) -> fmt::Result {
+        let format = lookup(self.id).ok_or(fmt::Error)?;
+        decode(format, self.args.as_bytes(), writer)
+    }
+}
+
+/// Encodes the arguments of an interned log. Used by the interned logging macros.
+#[doc(hidden)]
+pub struct ArgEncoder {
+    len: usize,
+    inline: [u8; INLINE_ARGS],
+    heap: Vec<u8>,
+}
+
+impl ArgEncoder {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            len: 0,
+            inline: [0; INLINE_ARGS],
+            heap: Vec::new(),
+        }
+    }
+
+    #[inline]
+    fn extend(&mut self, bytes: &[u8]) {
+        let end = self.len + bytes.len();
+        if end <= INLINE_ARGS {
+            self.inline[self.len..end].copy_from_slice(bytes);
+        } else {
+            if self.heap.is_empty() {
+                self.heap.extend_from_slice(&self.inline[..self.len]);
+            }
+            self.heap.extend_from_slice(bytes);
+        }
+        self.len = end;
+    }
+
+    #[inline]
+    fn varint(&mut self, mut value: u64) {
+        let mut buf = [0; 10];
+        let mut len = 0;
+        while value >= 0x80 {
+            buf[len] = value as u8 | 0x80;
+            value >>= 7;
+            len += 1;
+        }
+        buf[len] = value as u8;
+        self.extend(&buf[..=len]);
+    }
+
+    #[inline]
+    fn tagged_varint(&mut self, tag: u8, value: u64) {
+        self.extend(&[tag]);
+        self.varint(value);
+    }
+
+    #[inline]
+    fn finish(self) -> PackedArgs {
+        if self.len <= INLINE_ARGS {
+            PackedArgs::Inline {
+                len: self.len as u8,
+                bytes: self.inline,
+            }
+        } else {
+            PackedArgs::Heap(self.heap.into_boxed_slice())
+        }
+    }
+
+    /// Finishes encoding the arguments of a log whose format string is `format`.
+    #[inline]
+    pub(crate) fn pack(self, format: &InternedFormat) -> Packed {
+        Packed {
+            id: format.id(),
+            args: self.finish(),
+        }
+    }
+}
+
+impl Default for ArgEncoder {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A value which can be an argument of the interned logging macros. Implemented for integers up to
+/// 64 bits, floats, `bool`, `char` and strings.
+pub trait InternedArg {
+    #[doc(hidden)]
+    fn encode(&self, encoder: &mut ArgEncoder);
+}
+
+macro_rules! impl_unsigned {
+    ($($ty:ty => $tag:expr),*) => {
+        $(
+            impl InternedArg for $ty {
+                #[inline]
+                fn encode(&self, encoder: &mut ArgEncoder) {
+                    encoder.tagged_varint($tag, *self as u64);
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_signed {
+    ($($ty:ty => $tag:expr),*) => {
+        $(
+            impl InternedArg for $ty {
+                #[inline]
+                fn encode(&self, encoder: &mut ArgEncoder) {
+                    let value = *self as i64;
+                    encoder.tagged_varint($tag, ((value << 1) ^ (value >> 63)) as u64);
+                }
+            }
+        )*
+    };
+}
+
+impl_unsigned!(u8 => TAG_U8, u16 => TAG_U16, u32 => TAG_U32, u64 => TAG_U64, usize => TAG_U64);
+impl_signed!(i8 => TAG_I8, i16 => TAG_I16, i32 => TAG_I32, i64 => TAG_I64, isize => TAG_I64);
+
+impl InternedArg for f32 {
+    #[inline]
+    fn encode(&self, encoder: &mut ArgEncoder) {
+        encoder.extend(&[TAG_F32]);
+        encoder.extend(&self.to_le_bytes());
+    }
+}
+
+impl InternedArg for f64 {
+    #[inline]
+    fn encode(&self, encoder: &mut ArgEncoder) {
+        encoder.extend(&[TAG_F64]);
+        encoder.extend(&self.to_le_bytes());
+    }
+}
+
+impl InternedArg for bool {
+    #[inline]
+    fn encode(&self, encoder: &mut ArgEncoder) {
+        encoder.extend(&[TAG_BOOL, *self as u8]);
+    }
+}
+
+impl InternedArg for char {
+    #[inline]
+    fn encode(&self, encoder: &mut ArgEncoder) {
+        encoder.tagged_varint(TAG_CHAR, u64::from(*self));
+    }
+}
+
+impl InternedArg for str {
+    #[inline]
+    fn encode(&self, encoder: &mut ArgEncoder) {
+        encoder.tagged_varint(TAG_STR, self.len() as u64);
+        encoder.extend(self.as_bytes());
+    }
+}
+
+impl InternedArg for String {
+    #[inline]
+    fn encode(&self, encoder: &mut ArgEncoder) {
+        self.as_str().encode(encoder);
+    }
+}
+
+impl<T> InternedArg for &T
+where
+    T: InternedArg + ?Sized,
+{
+    #[inline]
+    fn encode(&self, encoder: &mut ArgEncoder) {
+        (**self).encode(encoder);
+    }
+}
+
+/// A decoded argument.
+#[derive(Debug, Clone, Copy)]
+enum Arg<'a> {
+    Unsigned(u64),
+    Signed { bits: u32, value: i64 },
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    Char(char),
+    Str(&'a str),
+}
+
+impl Arg<'_> {
+    fn is_numeric(self) -> bool {
+        matches!(
+            self,
+            Self::Unsigned(_) | Self::Signed { .. } | Self::F32(_) | Self::F64(_)
+        )
+    }
+
+    fn is_negative(self) -> bool {
+        match self {
+            Self::Signed { value, .. } => value < 0,
+            Self::F32(value) => value.is_sign_negative(),
+            Self::F64(value) => value.is_sign_negative(),
+            _ => false,
+        }
+    }
+
+    /// The bits of an integer, as its unsigned counterpart.
+    fn bits(self) -> Option<u64> {
+        match self {
+            Self::Unsigned(value) => Some(value),
+            Self::Signed { bits, value } => Some(value as u64 & (u64::MAX >> (64 - bits))),
+            _ => None,
+        }
+    }
+}
+
+/// A cursor over encoded arguments.
+struct Args<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Args<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], fmt::Error> {
+        if self.bytes.len() < len {
+            return Err(fmt::Error);
+        }
+
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<u64, fmt::Error> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(fmt::Error)
+    }
+
+    fn signed(&mut self) -> Result<i64, fmt::Error> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn next(&mut self) -> Result<Arg<'a>, fmt::Error> {
+        let tag = self.take(1)?[0];
+        Ok(match tag {
+            TAG_U8..=TAG_U64 => Arg::Unsigned(self.varint()?),
+            TAG_I8..=TAG_I64 => Arg::Signed {
+                bits: 8 << (tag - TAG_I8),
+                value: self.signed()?,
+            },
+            TAG_F32 => Arg::F32(f32::from_le_bytes(
+                self.take(4)?.try_into().map_err(|_| fmt::Error)?,
+            )),
+            TAG_F64 => Arg::F64(f64::from_le_bytes(
+                self.take(8)?.try_into().map_err(|_| fmt::Error)?,
+            )),
+            TAG_BOOL => Arg::Bool(self.take(1)?[0] != 0),
+            TAG_CHAR => Arg::Char(
+                u32::try_from(self.varint()?)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(fmt::Error)?,
+            ),
+            TAG_STR => {
+                let len = usize::try_from(self.varint()?).map_err(|_| fmt::Error)?;
+                Arg::Str(std::str::from_utf8(self.take(len)?).map_err(|_| fmt::Error)?)
+            }
+            _ => return Err(fmt::Error),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Display,
+    Debug,
+    LowerHex,
+    UpperHex,
+    Binary,
+    Octal,
+}
+
+/// A parsed `{:...}` placeholder.
+#[derive(Debug, Clone, Copy)]
+struct Spec {
+    fill: char,
+    align: Option<Align>,
+    plus: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    kind: Kind,
+}
+
+impl Spec {
+    /// Parses the contents of a placeholder. Only implicitly positioned arguments are supported.
+    fn parse(placeholder: &str) -> Result<Self, fmt::Error> {
+        let spec = match placeholder.split_once(':') {
+            Some(("", spec)) => spec,
+            None if placeholder.is_empty() => "",
+            _ => return Err(fmt::Error),
+        };
+
+        let mut result = Self {
+            fill: ' ',
+            align: None,
+            plus: false,
+            alternate: false,
+            zero: false,
+            width: 0,
+            precision: None,
+            kind: Kind::Display,
+        };
+
+        let align = |c| match c {
+            '<' => Some(Align::Left),
+            '^' => Some(Align::Center),
+            '>' => Some(Align::Right),
+            _ => None,
+        };
+
+        let mut rest = spec;
+        let mut chars = rest.chars();
+        let first = chars.next();
+        if let Some(a) = chars.next().and_then(align) {
+            result.fill = first.expect("there's a char before");
+            result.align = Some(a);
+            rest = chars.as_str();
+        } else if let Some(a) = first.and_then(align) {
+            result.align = Some(a);
+            rest = &rest[1..];
+        }
+
+        if let Some(r) = rest.strip_prefix('+') {
+            result.plus = true;
+            rest = r;
+        }
+        if let Some(r) = rest.strip_prefix('#') {
+            result.alternate = true;
+            rest = r;
+        }
+        if let Some(r) = rest.strip_prefix('0') {
+            result.zero = true;
+            rest = r;
+        }
+
+        let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let end = digits(rest);
+        if end > 0 {
+            result.width = rest[..end].parse().map_err(|_| fmt::Error)?;
+            rest = &rest[end..];
+        }
+
+        if let Some(r) = rest.strip_prefix('.') {
+            let end = digits(r);
+            result.precision = Some(r[..end].parse().map_err(|_| fmt::Error)?);
+            rest = &r[end..];
+        }
+
+        result.kind = match rest {
+            "" => Kind::Display,
+            "?" => Kind::Debug,
+            "x" => Kind::LowerHex,
+            "X" => Kind::UpperHex,
+            "b" => Kind::Binary,
+            "o" => Kind::Octal,
+            _ => return Err(fmt::Error),
+        };
+
+        Ok(result)
+    }
+
+    /// Writes `arg` without padding into `buf`, returning the length of its sign and prefix.
+    fn write_unpadded(&self, buf: &mut String, arg: Arg) -> Result<usize, fmt::Error> {
+        if self.plus && arg.is_numeric() && !arg.is_negative() {
+            buf.push('+');
+        }
+
+        let radix = |buf: &mut String, prefix: &str| -> Result<usize, fmt::Error> {
+            let bits = arg.bits().ok_or(fmt::Error)?;
+            if self.alternate {
+                buf.push_str(prefix);
+            }
+
+            let prefix_len = buf.len();
+            match self.kind {
+                Kind::LowerHex => write!(buf, "{bits:x}"),
+                Kind::UpperHex => write!(buf, "{bits:X}"),
+                Kind::Binary => write!(buf, "{bits:b}"),
+                _ => write!(buf, "{bits:o}"),
+            }?;
+
+            Ok(prefix_len)
+        };
+
+        match self.kind {
+            Kind::LowerHex | Kind::UpperHex => return radix(buf, "0x"),
+            Kind::Binary => return radix(buf, "0b"),
+            Kind::Octal => return radix(buf, "0o"),
+            Kind::Display | Kind::Debug => (),
+        }
+
+        let debug = self.kind == Kind::Debug;
+        match (arg, self.precision) {
+            (Arg::Unsigned(value), _) => write!(buf, "{value}")?,
+            (Arg::Signed { value, .. }, _) => write!(buf, "{value}")?,
+            (Arg::F32(value), Some(precision)) if debug => write!(buf, "{value:.precision$?}")?,
+            (Arg::F32(value), Some(precision)) => write!(buf, "{value:.precision$}")?,
+            (Arg::F32(value), None) if debug => write!(buf, "{value:?}")?,
+            (Arg::F32(value), None) => write!(buf, "{value}")?,
+            (Arg::F64(value), Some(precision)) if debug => write!(buf, "{value:.precision$?}")?,
+            (Arg::F64(value), Some(precision)) => write!(buf, "{value:.precision$}")?,
+            (Arg::F64(value), None) if debug => write!(buf, "{value:?}")?,
+            (Arg::F64(value), None) => write!(buf, "{value}")?,
+            (Arg::Bool(value), _) => write!(buf, "{value}")?,
+            (Arg::Char(value), _) if debug => write!(buf, "{value:?}")?,
+            (Arg::Char(value), _) => buf.push(value),
+            (Arg::Str(value), _) if debug => write!(buf, "{value:?}")?,
+            (Arg::Str(value), Some(precision)) => write!(buf, "{value:.precision$}")?,
+            (Arg::Str(value), None) => buf.push_str(value),
+        }
+
+        // the sign, if any
+        Ok(usize::from(buf.starts_with(['+', '-']) && arg.is_numeric()))
+    }
+
+    fn write(&self, writer: &mut dyn Write, buf: &mut String, arg: Arg) -> fmt::Result {
+        buf.clear();
+        let prefix_len = self.write_unpadded(buf, arg)?;
+
+        let len = buf.chars().count();
+        let padding = self.width.saturating_sub(len);
+        if padding == 0 {
+            return writer.write_str(buf);
+        }
+
+        if self.zero && arg.is_numeric() {
+            writer.write_str(&buf[..prefix_len])?;
+            (0..padding).try_for_each(|_| writer.write_char('0'))?;
+            return writer.write_str(&buf[prefix_len..]);
+        }
+
+        let default = if arg.is_numeric() {
+            Align::Right
+        } else {
+            Align::Left
+        };
+        let (before, after) = match self.align.unwrap_or(default) {
+            Align::Left => (0, padding),
+            Align::Center => (padding / 2, padding - padding / 2),
+            Align::Right => (padding, 0),
+        };
+
+        (0..before).try_for_each(|_| writer.write_char(self.fill))?;
+        writer.write_str(buf)?;
+        (0..after).try_for_each(|_| writer.write_char(self.fill))
+    }
+}
+
+/// Whether [`decode`] can format `format`, given the right arguments. This follows the same
+/// grammar as [`decode`] and [`Spec::parse`], but is usable in constant expressions.
+const fn is_supported(format: &str) -> bool {
+    let bytes = format.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' if i + 1 < bytes.len() && bytes[i + 1] == b'{' => i += 2,
+            b'}' if i + 1 < bytes.len() && bytes[i + 1] == b'}' => i += 2,
+            b'}' => return false,
+            b'{' => {
+                let mut end = i + 1;
+                while end < bytes.len() && bytes[end] != b'}' {
+                    end += 1;
+                }
+
+                if end == bytes.len() || !is_supported_spec(bytes, i + 1, end) {
+                    return false;
+                }
+                i = end + 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    true
+}
+
+/// Whether `bytes[start..end]`, the contents of a placeholder, is something [`Spec::parse`]
+/// accepts.
+const fn is_supported_spec(bytes: &[u8], start: usize, end: usize) -> bool {
+    if start == end {
+        return true;
+    }
+    if bytes[start] != b':' {
+        return false;
+    }
+
+    const fn is_align(b: u8) -> bool {
+        matches!(b, b'<' | b'^' | b'>')
+    }
+
+    let mut i = start + 1;
+
+    // the fill can be any char, so skip as many bytes as its UTF-8 encoding takes
+    let fill_len = match bytes[i] {
+        0xF0.. => 4,
+        0xE0.. => 3,
+        0xC0.. => 2,
+        _ => 1,
+    };
+    if i + fill_len < end && is_align(bytes[i + fill_len]) {
+        i += fill_len + 1;
+    } else if i < end && is_align(bytes[i]) {
+        i += 1;
+    }
+
+    if i < end && bytes[i] == b'+' {
+        i += 1;
+    }
+    if i < end && bytes[i] == b'#' {
+        i += 1;
+    }
+    if i < end && bytes[i] == b'0' {
+        i += 1;
+    }
+
+    i = skip_number(bytes, i, end);
+    if i < end && bytes[i] == b'.' {
+        let digits = i + 1;
+        i = skip_number(bytes, digits, end);
+        if i == digits {
+            return false;
+        }
+    }
+
+    match end - i {
+        0 => true,
+        1 => matches!(bytes[i], b'?' | b'x' | b'X' | b'b' | b'o'),
+        _ => false,
+    }
+}
+
+/// Skips the ASCII digits of `bytes[start..end]`, returning the index of the first other byte.
+/// Numbers which don't fit in a `usize` stop at the digit that overflows, so they're rejected.
+const fn skip_number(bytes: &[u8], start: usize, end: usize) -> usize {
+    let mut i = start;
+    let mut value: usize = 0;
+    while i < end && bytes[i].is_ascii_digit() {
+        value = match value.checked_mul(10) {
+            Some(value) => match value.checked_add((bytes[i] - b'0') as usize) {
+                Some(value) => value,
+                None => return i,
+            },
+            None => return i,
+        };
+        i += 1;
+    }
+
+    i
+}
+
+/// Formats `format` with the encoded arguments `args`. Fails if the format string uses anything
+/// beyond implicitly positioned arguments, or if it doesn't match the arguments.
+pub(crate) fn decode(format: &str, args: &[u8], writer: &mut dyn Write) -> fmt::Result {
+    let mut args = Args { bytes: args };
+    let mut buf = String::new();
+    let mut rest = format;
+
+    while let Some(index) = rest.find(['{', '}']) {
+        writer.write_str(&rest[..index])?;
+
+        let brace = rest.as_bytes()[index];
+        rest = &rest[index + 1..];
+        if rest.as_bytes().first() == Some(&brace) {
+            writer.write_char(brace as char)?;
+            rest = &rest[1..];
+            continue;
+        }
+
+        if brace == b'}' {
+            return Err(fmt::Error);
+        }
+
+        let end = rest.find('}').ok_or(fmt::Error)?;
+        let spec = Spec::parse(&rest[..end])?;
+        rest = &rest[end + 1..];
+
+        spec.write(writer, &mut buf, args.next()?)?;
+    }
+
+    writer.write_str(rest)?;
+    if args.bytes.is_empty() {
+        Ok(())
+    } else {
+        Err(fmt::Error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, is_supported, ArgEncoder, InternedArg};
+
+    fn render(format: &str, args: &[&dyn InternedArg]) -> Option<String> {
+        let mut encoder = ArgEncoder::new();
+        for arg in args {
+            arg.encode(&mut encoder);
+        }
+        let packed = encoder.finish();
+
+        let mut out = String::new();
+        decode(format, packed.as_bytes(), &mut out).ok()?;
+        Some(out)
+    }
+
+    macro_rules! check {
+        ($fmt:literal $(, $arg:expr)*) => {
+            assert_eq!(
+                render($fmt, &[$(&$arg),*]).as_deref(),
+                Some(format!($fmt $(, $arg)*).as_str()),
+                "{}",
+                $fmt
+            );
+        };
+    }
+
+    #[test]
+    fn matches_std() {
+        check!("plain {{text}}");
+        check!("{} {} {} {}", 1u8, -2i16, 3u32, -4i64);
+        check!(
+            "{:x} {:X} {:#x} {:#010x} {:08X}",
+            255u8,
+            -1i8,
+            0xBEEFu16,
+            0x1F80u32,
+            u64::MAX
+        );
+        check!("{:b} {:#b} {:o} {:#o}", 5u8, -1i8, 8u32, 8i64);
+        check!(
+            "{:5}|{:<5}|{:^5}|{:>5}|{:*^7}",
+            42u32,
+            42i32,
+            42u8,
+            "ab",
+            "mid"
+        );
+        check!("{:+} {:+} {:05} {:+06}", 3i32, -3i32, -42i32, 7u8);
+        check!(
+            "{} {:?} {:.3} {:8.2} {:?}",
+            1.1f32,
+            1.0f64,
+            2.0f64 / 3.0,
+            -0.5f32,
+            1e21f64
+        );
+        check!("{} {:?} {} {:?} {}", 'c', '\n', true, "quo\"te", "text");
+        check!("{:.2} {:>6.1}|", "truncated", "xyz");
+        check!("{:?} {}", String::from("owned"), usize::MAX);
+
+        let long = "a string which doesn't fit inline";
+        check!("{} {} {}", long, 1u8, long);
+    }
+
+    #[test]
+    fn mismatches() {
+        assert_eq!(render("{}", &[]), None);
+        assert_eq!(render("{}", &[&1u8, &2u8]), None);
+        assert_eq!(render("{:x}", &[&"str"]), None);
+        assert_eq!(render("{0}", &[&1u8]), None);
+        assert_eq!(render("{name}", &[&1u8]), None);
+        assert_eq!(render("{:e}", &[&1.0f64]), None);
+        assert_eq!(render("unclosed {", &[]), None);
+        assert_eq!(render("unopened }", &[]), None);
+    }
+
+    #[test]
+    fn supported_formats() {
+        for format in [
+            "",
+            "{{}} {{",
+            "{} {:?} {:x} {:X} {:b} {:o}",
+            "{:+#010x} {:>8} {:*^9.3} {:é<4} {:.0?}",
+            "{:0} {:#?} {:.2} {: >}",
+        ] {
+            assert!(is_supported(format), "{format}");
+        }
+
+        for format in [
+            "{x}",
+            "{0}",
+            "{name}",
+            "{:e}",
+            "{:w$}",
+            "{:.*}",
+            "{:.}",
+            "{:99999999999999999999999}",
+            "unclosed {",
+            "unopened }",
+            "{:",
+        ] {
+            assert!(!is_supported(format), "{format}");
+        }
+    }
+}
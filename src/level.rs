@@ -0,0 +1,266 @@
+use std::{
+    cmp::Ordering,
+    sync::{
+        atomic::{self, AtomicU32},
+        Mutex,
+    },
+};
+
+/// The level of a log. Levels are ordered by their severity, from least to most severe.
+///
+/// Besides the built-in levels, custom levels with their own name and severity can be defined
+/// with [`Level::custom`]:
+///
+/// ```
+/// use qlog::Level;
+///
+/// // noisier than `Debug`, but not as noisy as `Trace`
+/// const VERBOSE: Level = Level::custom("Verbose", 15);
+///
+/// assert!(Level::Trace < VERBOSE && VERBOSE < Level::Debug);
+/// assert_eq!(VERBOSE.to_string(), "Verbose");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Custom(CustomLevel),
+}
+
+impl Level {
+    /// Defines a custom level with the given `name` and numeric `severity`.
+    #[inline]
+    pub const fn custom(name: &'static str, severity: u8) -> Self {
+        Self::Custom(CustomLevel { name, severity })
+    }
+
+    /// The numeric severity of this level. Severities of the built-in levels are spaced out so
+    /// that there's room for custom levels in between:
+    ///
+    /// | Level   | Severity |
+    /// |---------|----------|
+    /// | `Trace` | 10       |
+    /// | `Debug` | 20       |
+    /// | `Info`  | 30       |
+    /// | `Warn`  | 40       |
+    /// | `Error` | 50       |
+    #[inline]
+    pub const fn severity(self) -> u8 {
+        match self {
+            Self::Trace => 10,
+            Self::Debug => 20,
+            Self::Info => 30,
+            Self::Warn => 40,
+            Self::Error => 50,
+            Self::Custom(custom) => custom.severity,
+        }
+    }
+
+    /// Returns the built-in level with the given numeric severity, if any.
+    #[inline]
+    pub const fn from_severity(severity: u8) -> Option<Self> {
+        Some(match severity {
+            10 => Self::Trace,
+            20 => Self::Debug,
+            30 => Self::Info,
+            40 => Self::Warn,
+            50 => Self::Error,
+            _ => return None,
+        })
+    }
+
+    /// The name of this level.
+    #[inline]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Trace => "Trace",
+            Self::Debug => "Debug",
+            Self::Info => "Info",
+            Self::Warn => "Warn",
+            Self::Error => "Error",
+            Self::Custom(custom) => custom.name,
+        }
+    }
+}
+
+impl AsRef<str> for Level {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self.name()
+    }
+}
+
+impl std::fmt::Display for Level {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.as_ref())
+    }
+}
+
+impl PartialOrd for Level {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Level {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        // levels with the same severity are ordered arbitrarily, but consistently with `Eq`
+        self.severity()
+            .cmp(&other.severity())
+            .then_with(|| matches!(self, Self::Custom(_)).cmp(&matches!(other, Self::Custom(_))))
+            .then_with(|| self.name().cmp(other.name()))
+    }
+}
+
+/// A user-defined [`Level`]. See [`Level::custom`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CustomLevel {
+    name: &'static str,
+    severity: u8,
+}
+
+impl CustomLevel {
+    /// The name of this level.
+    #[inline]
+    pub const fn name(self) -> &'static str {
+        self.name
+    }
+
+    /// The numeric severity of this level.
+    #[inline]
+    pub const fn severity(self) -> u8 {
+        self.severity
+    }
+}
+
+/// A [`Level`] which can be read and changed atomically, used for the minimum level of a logger.
+/// It's kept in a single atomic holding the severity and, for custom levels, an index into the
+/// list of every custom level stored so far, so that the severity checked when logging never
+/// disagrees with the level.
+pub(crate) struct AtomicLevel(AtomicU32);
+
+impl AtomicLevel {
+    pub fn new(level: Level) -> Self {
+        Self(AtomicU32::new(Self::encode(level)))
+    }
+
+    /// The severity of the level, which is all logging needs.
+    #[inline]
+    pub fn severity(&self) -> u8 {
+        self.0.load(atomic::Ordering::Relaxed) as u8
+    }
+
+    pub fn load(&self) -> Level {
+        let encoded = self.0.load(atomic::Ordering::Relaxed);
+        match (encoded >> 8) as usize {
+            0 => Level::from_severity(encoded as u8).expect("severity is a built-in level's"),
+            index => Level::Custom(Self::custom_levels()[index - 1]),
+        }
+    }
+
+    pub fn store(&self, level: Level) {
+        self.0.store(Self::encode(level), atomic::Ordering::Relaxed);
+    }
+
+    fn encode(level: Level) -> u32 {
+        let index = match level {
+            Level::Custom(custom) => {
+                let mut levels = Self::custom_levels();
+                let index = match levels.iter().position(|&l| l == custom) {
+                    Some(index) => index,
+                    None => {
+                        levels.push(custom);
+                        levels.len() - 1
+                    }
+                };
+
+                index as u32 + 1
+            }
+            _ => 0,
+        };
+
+        index << 8 | level.severity() as u32
+    }
+
+    /// Every custom level stored so far. Programs only define a handful of them, so this stays
+    /// small.
+    fn custom_levels() -> std::sync::MutexGuard<'static, Vec<CustomLevel>> {
+        static LEVELS: Mutex<Vec<CustomLevel>> = Mutex::new(Vec::new());
+        LEVELS.lock().expect("lock is not poisoned")
+    }
+}
+
+/// The severity of the least severe level allowed by the `max_level_*` and `release_max_level_*`
+/// cargo features, or a value above every severity if logging is statically disabled.
+const STATIC_MIN_SEVERITY: u16 = {
+    const OFF: u16 = u8::MAX as u16 + 1;
+    const fn severity(level: Level) -> u16 {
+        level.severity() as u16
+    }
+
+    if cfg!(all(
+        not(debug_assertions),
+        feature = "release_max_level_off"
+    )) {
+        OFF
+    } else if cfg!(all(
+        not(debug_assertions),
+        feature = "release_max_level_error"
+    )) {
+        severity(Level::Error)
+    } else if cfg!(all(
+        not(debug_assertions),
+        feature = "release_max_level_warn"
+    )) {
+        severity(Level::Warn)
+    } else if cfg!(all(
+        not(debug_assertions),
+        feature = "release_max_level_info"
+    )) {
+        severity(Level::Info)
+    } else if cfg!(all(
+        not(debug_assertions),
+        feature = "release_max_level_debug"
+    )) {
+        severity(Level::Debug)
+    } else if cfg!(all(
+        not(debug_assertions),
+        feature = "release_max_level_trace"
+    )) {
+        severity(Level::Trace)
+    } else if cfg!(feature = "max_level_off") {
+        OFF
+    } else if cfg!(feature = "max_level_error") {
+        severity(Level::Error)
+    } else if cfg!(feature = "max_level_warn") {
+        severity(Level::Warn)
+    } else if cfg!(feature = "max_level_info") {
+        severity(Level::Info)
+    } else if cfg!(feature = "max_level_debug") {
+        severity(Level::Debug)
+    } else if cfg!(feature = "max_level_trace") {
+        severity(Level::Trace)
+    } else {
+        0
+    }
+};
+
+/// Whether logs with the given `level` are allowed by the `max_level_*` and `release_max_level_*`
+/// cargo features. For example, with `max_level_info` enabled, only logs with a severity of at
+/// least [`Level::Info`] are allowed. The `release_*` variants only apply when debug assertions
+/// are disabled, and take precedence over the others there.
+///
+/// The logging macros check this before anything else, so with a constant level, disabled logs
+/// compile down to nothing - though their arguments are still type-checked.
+#[inline(always)]
+// without any of the features, the threshold is 0 and this is always true
+#[allow(clippy::absurd_extreme_comparisons)]
+pub const fn statically_enabled(level: Level) -> bool {
+    level.severity() as u16 >= STATIC_MIN_SEVERITY
+}
@@ -1,50 +1,230 @@
+#[macro_use]
+mod macros;
+
+mod binary;
+mod builder;
+mod callsite;
+mod content;
+mod error;
+mod field;
+mod format;
+mod intern;
+mod interned;
+mod level;
+mod logfmt;
 mod loggable;
+mod logs;
+mod ratelimit;
+#[cfg(feature = "serde")]
+mod serde_impls;
+mod sink;
+mod worker;
+
+pub use binary::BinaryReader;
+pub use builder::{Backpressure, LoggerBuilder};
+pub use callsite::{Callsite, Location};
+pub use error::{LogError, ParseFormatError};
+pub use field::{Field, Hex, Value};
+pub use format::{DisplayWith, Format};
+#[doc(hidden)]
+pub use interned::ArgEncoder;
+pub use interned::{InternedArg, InternedFormat};
+pub use level::{statically_enabled, CustomLevel, Level};
+pub use logfmt::Logfmt;
+pub use loggable::{Loggable, Structured};
+pub use logs::{Logs, LogsIter};
+#[doc(hidden)]
+pub use ratelimit::{CallsiteEvery, CallsiteEveryN, CallsiteOnce};
+#[cfg(feature = "serde")]
+pub use sink::JsonLinesSink;
+pub use sink::{BinaryWriter, ConsoleSink, ErrorCount, FileSink, LogfmtSink, Rotation, Sink};
 
 pub use chrono;
 use chrono::Utc;
-use loggable::{ErasedLoggable, Loggable};
+use content::Content;
+use loggable::ErasedLoggable;
 use std::{
-    collections::VecDeque,
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
-use strum::AsRefStr;
-
-/// The level of a log.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr)]
-pub enum Level {
-    Debug,
-    Info,
-    Warn,
-    Error,
-}
+use worker::{LogBuilder, Message, Payload, Worker};
 
-impl std::fmt::Display for Level {
-    #[inline]
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.as_ref())
-    }
-}
+/// Appended to the message of a [`Log`] whose [`Loggable`] failed to format itself, after whatever
+/// it managed to write before failing.
+pub const FORMAT_ERROR: &str = "<formatting error>";
 
-#[derive(Debug, Clone)]
+/// A processed log, as kept by a [`Logger`] and handed to its [`Sink`]s.
+///
+/// # Reading logs back
+///
+/// Logs hold `&'static str`s for their target, file and field names and custom level names, as
+/// these are usually string literals. When logs are read back, by deserializing them or with a
+/// [`BinaryReader`], these strings are interned instead: each distinct one is leaked once and
+/// reused afterwards. This is fine for logs written by a program, which only has so many call
+/// sites, but means you shouldn't read untrusted input, as memory would grow with every new name
+/// it makes up.
+#[derive(Clone)]
 pub struct Log {
     /// The time this log was registered. This is _not_ the same as the time it was `.log`ged, as it
     /// might take some time for it to actually be processed by the backing thread.
     pub time: chrono::DateTime<Utc>,
+    /// The time the last repetition of this log was registered, if the logger collapses repeated
+    /// logs (see [`LoggerBuilder::dedupe`]). Otherwise, the same as `time`.
+    pub last_time: chrono::DateTime<Utc>,
+    /// How many times this log was repeated right after itself, if the logger collapses repeated
+    /// logs (see [`LoggerBuilder::dedupe`]). Otherwise, always zero.
+    pub repeat_count: u32,
     pub level: Level,
-    pub message: String,
+    /// The module path of the place this log was emitted from.
+    pub target: &'static str,
+    /// The place in the source code this log was emitted from.
+    pub location: Location,
+    content: Content,
+}
+
+impl Log {
+    /// Creates a log which was registered at `time` and wasn't repeated.
+    #[inline]
+    pub fn new(
+        time: chrono::DateTime<Utc>,
+        level: Level,
+        target: &'static str,
+        location: Location,
+        message: impl Into<String>,
+        fields: Vec<Field>,
+    ) -> Self {
+        Self::with_content(
+            time,
+            level,
+            target,
+            location,
+            Content::new(message.into(), fields),
+        )
+    }
+
+    #[inline]
+    pub(crate) fn with_content(
+        time: chrono::DateTime<Utc>,
+        level: Level,
+        target: &'static str,
+        location: Location,
+        content: Content,
+    ) -> Self {
+        Self {
+            time,
+            last_time: time,
+            repeat_count: 0,
+            level,
+            target,
+            location,
+            content,
+        }
+    }
+
+    /// Turns this into a new log which was registered at `time` and wasn't repeated, keeping its
+    /// content.
+    #[inline]
+    pub(crate) fn reset(
+        &mut self,
+        time: chrono::DateTime<Utc>,
+        level: Level,
+        target: &'static str,
+        location: Location,
+    ) {
+        self.time = time;
+        self.last_time = time;
+        self.repeat_count = 0;
+        self.level = level;
+        self.target = target;
+        self.location = location;
+    }
+
+    /// The message of this log. With [deferred formatting](LoggerBuilder::deferred_formatting),
+    /// this formats it if it hasn't been yet.
+    #[inline]
+    pub fn message(&self) -> &str {
+        self.content.message()
+    }
+
+    /// The structured fields attached to this log, in the order they were given. With
+    /// [deferred formatting](LoggerBuilder::deferred_formatting), this formats the log if it
+    /// hasn't been yet.
+    #[inline]
+    pub fn fields(&self) -> &[Field] {
+        self.content.fields()
+    }
+
+    /// The interned format string and arguments this log was created from, if any.
+    #[inline]
+    pub(crate) fn packed(&self) -> Option<&interned::Packed> {
+        self.content.as_packed()
+    }
+
+    /// The value of the field with the given `name`, if any.
+    #[inline]
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        self.fields()
+            .iter()
+            .find(|field| field.name == name)
+            .map(|field| &field.value)
+    }
+
+    /// Displays the log as a single line of text in the given `format`.
+    #[inline]
+    pub fn display_with<'a>(&'a self, format: &'a Format) -> DisplayWith<'a> {
+        DisplayWith { log: self, format }
+    }
+
+    /// Displays the log as a single line of [logfmt](https://brandur.org/logfmt). See [`Logfmt`].
+    #[inline]
+    pub fn logfmt(&self) -> Logfmt<'_> {
+        Logfmt { log: self }
+    }
+
+    /// Whether `other` is a repetition of this log, i.e. whether they only differ in time.
+    #[inline]
+    pub(crate) fn is_repeated_by(&self, other: &Log) -> bool {
+        self.level == other.level
+            && self.location == other.location
+            && self.target == other.target
+            && self.message() == other.message()
+            && self.fields() == other.fields()
+    }
 }
 
-struct LogBuilder {
-    level: Level,
-    loggable: ErasedLoggable,
+impl std::fmt::Debug for Log {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Log")
+            .field("time", &self.time)
+            .field("last_time", &self.last_time)
+            .field("repeat_count", &self.repeat_count)
+            .field("level", &self.level)
+            .field("target", &self.target)
+            .field("location", &self.location)
+            .field("message", &self.message())
+            .field("fields", &self.fields())
+            .finish()
+    }
+}
+
+/// Displays the log as a single line of text in the default [`Format`], such as
+/// `2024-03-01 12:34:56.789  Warn [emu::cpu] unhandled DMA channel channel=2`.
+impl std::fmt::Display for Log {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Format::default_ref().write(f, self)
+    }
 }
 
 /// A type which can be used for logging events. It is cheaply clonable and cloning it will create
 /// a logger that shares the same storage as this one for logs.
+///
+/// The backing thread is shut down once the last clone of a logger is dropped, after it has
+/// processed every log sent to it.
 #[derive(Clone)]
 pub struct Logger {
-    logs: Arc<Mutex<VecDeque<Log>>>,
-    sender: flume::Sender<LogBuilder>,
+    logs: Arc<Mutex<Logs>>,
+    worker: Arc<Worker>,
 }
 
 impl Logger {
@@ -54,307 +234,1002 @@ impl Logger {
     /// You can give this logger a limit, and when the limit is exceeded, the oldest logs are
     /// deleted when logging new things. *You should* give the logger a limit, since a limitless
     /// logger will only grow in memory usage unless you call `clear`.
+    ///
+    /// For more options, see [`Logger::builder`].
     pub fn new(limit: Option<usize>) -> Self {
-        let (sender, receiver) = flume::bounded::<LogBuilder>(u16::MAX as usize);
-        let logs = Arc::new(Mutex::new(VecDeque::with_capacity(limit.unwrap_or(0))));
-
-        std::thread::spawn({
-            let logs = logs.clone();
-
-            move || {
-                while let Ok(builder) = receiver.recv() {
-                    let mut buf = String::new();
-                    builder.loggable.log_to(&mut buf).expect("logging ok");
-                    buf.shrink_to_fit();
-
-                    let mut logs = logs.lock().expect("lock is not poisoned");
-                    if limit.is_some_and(|limit| logs.len() == limit) {
-                        logs.pop_front();
-                    }
-
-                    logs.push_back(Log {
-                        time: Utc::now(),
-                        level: builder.level,
-                        message: buf,
-                    });
-
-                    std::mem::drop(logs);
-                }
-            }
-        });
+        LoggerBuilder {
+            limit,
+            ..Default::default()
+        }
+        .build()
+    }
 
-        Self { logs, sender }
+    /// Creates a [`LoggerBuilder`] for configuring a new [`Logger`].
+    #[inline]
+    pub fn builder() -> LoggerBuilder {
+        LoggerBuilder::default()
     }
 
-    /// Logs a value `l` with the given [`Level`]. This method might allocate depending on the size
-    /// of `l` - values smaller than or equal to 24 bytes do not allocate.
+    /// Logs a value `l` with the given [`Level`], emitted from the given [`Callsite`] (see
+    /// [`callsite!`]). This method might allocate depending on the size of `l` - values smaller
+    /// than or equal to 24 bytes do not allocate.
+    ///
+    /// If the backing thread is falling behind, this follows the logger's [`Backpressure`] policy.
+    /// Logs sent after the logger has been [shut down](Logger::shutdown) are discarded.
     #[inline]
-    pub fn log<L>(&self, level: Level, l: L)
+    pub fn log<L>(&self, level: Level, callsite: &'static Callsite, l: L)
     where
         L: Loggable + 'static,
     {
-        self.sender
-            .send(LogBuilder {
-                level,
-                loggable: ErasedLoggable::new(l),
-            })
-            .expect("channel is open");
+        if !self.enabled(level) {
+            return;
+        }
+
+        self.worker.send_log(LogBuilder {
+            level,
+            callsite,
+            payload: Payload::Loggable(ErasedLoggable::new(l)),
+        });
     }
 
-    /// Calls a function with read access to all the [`Log`]s. Note that this might not show a
-    /// recently logged value as it might not have been processed by the backing thread yet.
+    /// Tries to log a value `l` with the given [`Level`] and [`Callsite`] without blocking,
+    /// regardless of the logger's [`Backpressure`] policy. Fails if the channel to the backing
+    /// thread is full or if the backing thread is gone.
+    ///
+    /// Logs below the minimum level of the logger are discarded and never fail.
+    #[inline]
+    pub fn try_log<L>(
+        &self,
+        level: Level,
+        callsite: &'static Callsite,
+        l: L,
+    ) -> Result<(), LogError>
+    where
+        L: Loggable + 'static,
+    {
+        if !self.enabled(level) {
+            return Ok(());
+        }
+
+        self.worker.try_send_log(LogBuilder {
+            level,
+            callsite,
+            payload: Payload::Loggable(ErasedLoggable::new(l)),
+        })
+    }
+
+    /// Logs an interned message with the given [`Level`] and [`Callsite`]. Used by
+    /// [`log_interned!`] and friends.
+    #[doc(hidden)]
+    #[inline]
+    pub fn log_interned(
+        &self,
+        level: Level,
+        callsite: &'static Callsite,
+        format: &'static InternedFormat,
+        args: ArgEncoder,
+    ) {
+        if !self.enabled(level) {
+            return;
+        }
+
+        self.worker.send_log(LogBuilder {
+            level,
+            callsite,
+            payload: Payload::Packed(args.pack(format)),
+        });
+    }
+
+    /// Whether logs with the given `level` are at or above the minimum level of the logger and
+    /// [statically enabled](statically_enabled), i.e. whether they would be accepted right now.
+    #[inline]
+    pub fn enabled(&self, level: Level) -> bool {
+        statically_enabled(level) && level.severity() >= self.worker.min_level.severity()
+    }
+
+    /// The minimum [`Level`] of the logs this logger accepts.
+    #[inline]
+    pub fn min_level(&self) -> Level {
+        self.worker.min_level.load()
+    }
+
+    /// Sets the minimum [`Level`] of the logs this logger (and all of its clones) accepts. Logs
+    /// with a lower level are discarded before being sent to the backing thread, and the logging
+    /// macros don't even evaluate their arguments for them.
+    #[inline]
+    pub fn set_min_level(&self, level: Level) {
+        self.worker.min_level.store(level);
+    }
+
+    /// How many logs were discarded because the channel to the backing thread was full, as
+    /// configured by [`LoggerBuilder::backpressure`].
+    #[inline]
+    pub fn dropped(&self) -> u64 {
+        self.worker.dropped()
+    }
+
+    /// Blocks until every log sent to this logger (by any of its clones) before this call has been
+    /// processed by the backing thread and its [`Sink`]s have been flushed. After this returns,
+    /// [`Logger::with_logs`] is guaranteed to see them.
+    pub fn flush(&self) {
+        let (done, wait) = flume::bounded(1);
+        if self.worker.sender.send(Message::Flush(done)).is_ok() {
+            // fails if the logger is shut down before reaching the barrier, which also means
+            // there's nothing left to wait for
+            let _ = wait.recv();
+        }
+    }
+
+    /// Like [`Logger::flush`], but gives up after `timeout` has elapsed. Returns whether the flush
+    /// completed in time.
+    pub fn flush_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let (done, wait) = flume::bounded(1);
+        if self
+            .worker
+            .sender
+            .send_deadline(Message::Flush(done), deadline)
+            .is_err()
+        {
+            return false;
+        }
+
+        wait.recv_deadline(deadline).is_ok()
+    }
+
+    /// Adds a [`Sink`] to the logger, which will be handed every log sent after this call. See
+    /// [`LoggerBuilder::sink`].
+    pub fn add_sink(&self, sink: impl Sink + 'static) {
+        let _ = self.worker.sender.send(Message::AddSink(Box::new(sink)));
+    }
+
+    /// Processes every log sent to this logger (by any of its clones) before this call, then stops
+    /// the backing thread and waits for it to finish. Logs sent afterwards are discarded.
+    ///
+    /// This is done automatically when the last clone of the logger is dropped, so you only need to
+    /// call it if you want to stop logging while clones are still around.
+    pub fn shutdown(&self) {
+        self.worker.shutdown();
+    }
+
+    /// Calls a function with read access to all the [`Log`]s, which are kept in [`Logs`]. Note
+    /// that this might not show a recently logged value as it might not have been processed by the
+    /// backing thread yet.
     #[inline]
     pub fn with_logs<F, O>(&self, f: F) -> O
     where
-        F: FnOnce(&VecDeque<Log>) -> O,
+        F: FnOnce(&Logs) -> O,
     {
         let logs = self.logs.lock().expect("lock is not poisoned");
         f(&logs)
     }
 
+    /// How much memory the logs kept by the logger take, in bytes, as counted by
+    /// [`LoggerBuilder::memory_budget`]. With deferred formatting, this grows as logs are read,
+    /// and can exceed the budget until the next log is stored. Spare capacity isn't counted, so
+    /// this is less than what's actually allocated.
+    #[inline]
+    pub fn memory_usage(&self) -> usize {
+        self.with_logs(|logs| logs.memory_usage())
+    }
+
+    /// Writes all the [`Log`]s as [JSON Lines](https://jsonlines.org), one JSON object per log.
+    /// Like [`Logger::with_logs`], this might not include recently logged values, and the backing
+    /// thread can't store new logs while this runs.
+    #[cfg(feature = "serde")]
+    pub fn export_jsonl(&self, mut writer: impl std::io::Write) -> std::io::Result<()> {
+        self.with_logs(|logs| {
+            logs.iter()
+                .try_for_each(|log| sink::write_json_line(&mut writer, log))
+        })?;
+
+        writer.flush()
+    }
+
     /// Clear the log buffer and shrink it.
     #[inline]
     pub fn clear(&self) {
-        let mut logs = self.logs.lock().expect("lock is not poisoned");
-        logs.clear();
-        logs.shrink_to_fit();
+        self.logs.lock().expect("lock is not poisoned").clear();
     }
 }
 
-#[macro_export]
-macro_rules! debug {
-    ($logger:expr, $s:literal) => {
-        $logger.log(
-            $crate::Level::Debug,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s),
-        );
+#[cfg(test)]
+mod test {
+    use crate::{
+        callsite, content::Content, loggable::ErasedLoggable, Backpressure, BinaryReader,
+        BinaryWriter, Field, Hex, Level, Location, Log, LogError, LogfmtSink, Loggable, Logger,
+        Sink, Value, FORMAT_ERROR,
     };
-    ($logger:expr, $s:literal, $_0:expr) => {{
-        let _0 = $_0;
-        $logger.log(
-            $crate::Level::Debug,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0),
-        );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        $logger.log(
-            $crate::Level::Debug,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1),
-        );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr, $_2:expr) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        let _2 = $_2;
-        $logger.log(
-            $crate::Level::Debug,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1, _2),
-        );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr, $_2:expr, $_3:expr) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        let _2 = $_2;
-        let _3 = $_3;
-        $logger.log(
-            $crate::Level::Debug,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1, _2, _3),
-        );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr, $_2:expr, $_3:expr, $_4:expr $(,)*) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        let _2 = $_2;
-        let _3 = $_3;
-        let _4 = $_4;
-        $logger.log(
-            $crate::Level::Debug,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1, _2, _3, _4),
-        );
-    }};
-}
-
-#[macro_export]
-macro_rules! info {
-    ($logger:expr, $s:literal) => {
-        $logger.log(
-            $crate::Level::Info,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s),
-        );
+    use chrono::{DateTime, TimeZone, Utc};
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
     };
-    ($logger:expr, $s:literal, $_0:expr) => {{
-        let _0 = $_0;
-        $logger.log(
-            $crate::Level::Info,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0),
-        );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        $logger.log(
-            $crate::Level::Info,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1),
-        );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr, $_2:expr) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        let _2 = $_2;
-        $logger.log(
-            $crate::Level::Info,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1, _2),
-        );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr, $_2:expr, $_3:expr) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        let _2 = $_2;
-        let _3 = $_3;
-        $logger.log(
-            $crate::Level::Info,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1, _2, _3),
-        );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr, $_2:expr, $_3:expr, $_4:expr $(,)*) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        let _2 = $_2;
-        let _3 = $_3;
-        let _4 = $_4;
-        $logger.log(
-            $crate::Level::Info,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1, _2, _3, _4),
-        );
-    }};
-}
 
-#[macro_export]
-macro_rules! warn {
-    ($logger:expr, $s:literal) => {
-        $logger.log(
-            $crate::Level::Warn,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s),
-        );
-    };
-    ($logger:expr, $s:literal, $_0:expr) => {{
-        let _0 = $_0;
-        $logger.log(
-            $crate::Level::Warn,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0),
-        );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        $logger.log(
-            $crate::Level::Warn,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1),
-        );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr, $_2:expr) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        let _2 = $_2;
-        $logger.log(
-            $crate::Level::Warn,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1, _2),
-        );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr, $_2:expr, $_3:expr) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        let _2 = $_2;
-        let _3 = $_3;
-        $logger.log(
-            $crate::Level::Warn,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1, _2, _3),
-        );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr, $_2:expr, $_3:expr, $_4:expr $(,)*) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        let _2 = $_2;
-        let _3 = $_3;
-        let _4 = $_4;
-        $logger.log(
-            $crate::Level::Warn,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1, _2, _3, _4),
-        );
-    }};
-}
+    /// The log unit tests start from: a warning from `emu::cpu` at `src/cpu.rs:42`, registered at
+    /// 2024-03-01 12:34:56.789 UTC.
+    pub(crate) fn test_log(message: &str, fields: Vec<Field>) -> Log {
+        let time = Utc.with_ymd_and_hms(2024, 3, 1, 12, 34, 56).unwrap()
+            + chrono::Duration::milliseconds(789);
+        let location = Location {
+            file: "src/cpu.rs",
+            line: 42,
+        };
+        Log::new(time, Level::Warn, "emu::cpu", location, message, fields)
+    }
+
+    /// Tweaks to [`test_log`], for tests which need a different level, time or content.
+    impl Log {
+        pub(crate) fn with_level(mut self, level: Level) -> Self {
+            self.level = level;
+            self
+        }
+
+        pub(crate) fn at(mut self, time: DateTime<Utc>) -> Self {
+            self.time = time;
+            self.last_time = time;
+            self
+        }
+
+        /// Replaces the message and fields with `l`, formatted when first read.
+        pub(crate) fn deferred<L>(mut self, l: L) -> Self
+        where
+            L: Loggable + 'static,
+        {
+            self.content = Content::deferred(ErasedLoggable::new(l));
+            self
+        }
+    }
+
+    #[test]
+    fn simple() {
+        let logger = Logger::new(None);
+        debug!(logger, "hello there: {}", 0);
+
+        logger.flush();
+
+        logger.with_logs(|logs| {
+            assert_eq!(logs[0].message(), "hello there: 0");
+        });
+    }
+
+    #[test]
+    fn callsite() {
+        let logger = Logger::new(None);
+        let line = line!() + 1;
+        warn!(logger, "unhandled DMA channel");
+        logger.flush();
+
+        logger.with_logs(|logs| {
+            assert_eq!(logs[0].target, "qlog::test");
+            assert_eq!(logs[0].location.file, file!());
+            assert_eq!(logs[0].location.line, line);
+        });
+    }
+
+    #[test]
+    fn fields() {
+        let logger = Logger::new(None);
+        let channel = 2;
+        info!(logger, "dma done"; channel = channel, bytes = 1024usize, addr = Hex(0x8001_0000));
+        warn!(logger, "dma {} failed", channel; reason = String::from("timeout"), retry = false,);
+        logger.flush();
+
+        logger.with_logs(|logs| {
+            assert_eq!(logs[0].message(), "dma done");
+            assert_eq!(logs[0].field("channel").and_then(Value::as_u64), Some(2));
+            assert_eq!(logs[0].field("bytes"), Some(&Value::U64(1024)));
+            assert_eq!(logs[0].field("addr").unwrap().to_string(), "0x80010000");
+
+            assert_eq!(logs[1].message(), "dma 2 failed");
+            assert_eq!(
+                logs[1].fields(),
+                [Field::new("reason", "timeout"), Field::new("retry", false)]
+            );
+        });
+    }
+
+    #[test]
+    fn variadic_macros() {
+        let logger = Logger::new(None);
+        let (pc, sp, ra, v0, a0, a1) = (0x8001_0000u32, 0x801f_fff0u32, 0x8000_1234u32, 1, 2, 3);
+        let mut order = Vec::new();
+        info!(logger, "pc={pc:08x} sp={:08x} ra={:08x} v0={} a0={} a1={} {x}", sp, ra, v0, a0, a1, x = {
+            order.push(0);
+            "end"
+        }; first = {
+            order.push(1);
+            1
+        });
+        info!(logger, "{} {} {0}", 1, 2,);
+        info!(logger, "{a}{b}", b = 2, a = 1; c = 3,);
+        logger.flush();
+
+        assert_eq!(order, [0, 1]);
+        logger.with_logs(|logs| {
+            assert_eq!(
+                logs[0].message(),
+                "pc=80010000 sp=801ffff0 ra=80001234 v0=1 a0=2 a1=3 end"
+            );
+            assert_eq!(logs[0].field("first"), Some(&Value::I64(1)));
+            assert_eq!(logs[1].message(), "1 2 1");
+            assert_eq!(logs[2].message(), "12");
+            assert_eq!(logs[2].field("c"), Some(&Value::I64(3)));
+        });
+    }
+
+    #[test]
+    fn dynamic_level() {
+        let logger = Logger::new(None);
+        logger.set_min_level(Level::Info);
+        for level in [Level::Debug, Level::Warn, Level::custom("Fatal", 60)] {
+            log!(logger, level, "{}", level; severity = level.severity());
+        }
+        logger.flush();
+
+        logger.with_logs(|logs| {
+            assert_eq!(logs.len(), 2);
+            assert_eq!(logs[0].level, Level::Warn);
+            assert_eq!(logs[1].message(), "Fatal");
+            assert_eq!(logs[1].field("severity"), Some(&Value::U64(60)));
+        });
+    }
+
+    #[test]
+    fn rate_limited() {
+        let logger = Logger::new(None);
+        logger.set_min_level(Level::Info);
+        for i in 0..10 {
+            warn_once!(logger, "once {}", i);
+            warn_every_n!(logger, 4, "every 4 {}", i);
+            warn_every!(logger, Duration::from_secs(3600), "every hour {}", i);
+            // disabled logs don't count
+            debug_once!(logger, "disabled once");
+            if i == 5 {
+                logger.set_min_level(Level::Trace);
+            }
+        }
+        logger.flush();
+
+        logger.with_logs(|logs| {
+            let messages: Vec<_> = logs.iter().map(|log| log.message()).collect();
+            assert_eq!(
+                messages,
+                [
+                    "once 0",
+                    "every 4 0",
+                    "every hour 0",
+                    "every 4 4",
+                    "disabled once",
+                    "every 4 8"
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn dedupe() {
+        let logger = Logger::builder().limit(2).dedupe(true).build();
+        for _ in 0..3 {
+            for i in 0..1000 {
+                warn!(logger, "unimplemented GTE opcode"; opcode = 0x3f);
+                if i % 500 == 0 {
+                    warn!(logger, "unimplemented GTE opcode"; opcode = 0x3e);
+                }
+            }
+
+            info!(logger, "frame done");
+        }
+        logger.flush();
+
+        logger.with_logs(|logs| {
+            assert_eq!(logs.len(), 2);
+            assert_eq!(logs[0].field("opcode"), Some(&Value::I64(0x3f)));
+            assert_eq!(logs[0].repeat_count, 498);
+            assert!(logs[0].last_time >= logs[0].time);
+            assert_eq!(logs[1].message(), "frame done");
+            assert_eq!(logs[1].repeat_count, 0);
+        });
+    }
+
+    #[derive(Clone, Default)]
+    struct Collect(Arc<Mutex<(Vec<String>, usize)>>);
+
+    impl Sink for Collect {
+        fn accept(&mut self, log: &Log) {
+            self.0.lock().unwrap().0.push(log.message().to_owned());
+        }
+
+        fn flush(&mut self) {
+            self.0.lock().unwrap().1 += 1;
+        }
+    }
+
+    #[test]
+    fn sinks() {
+        let first = Collect::default();
+        let second = Collect::default();
+        let logger = Logger::builder().limit(0).sink(first.clone()).build();
+
+        info!(logger, "a");
+        logger.add_sink(second.clone());
+        info!(logger, "b");
+        logger.flush();
 
-#[macro_export]
-macro_rules! error {
-    ($logger:expr, $s:literal) => {
-        $logger.log(
-            $crate::Level::Error,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s),
+        assert_eq!(
+            *first.0.lock().unwrap(),
+            (vec!["a".to_owned(), "b".to_owned()], 1)
         );
-    };
-    ($logger:expr, $s:literal, $_0:expr) => {{
-        let _0 = $_0;
-        $logger.log(
-            $crate::Level::Error,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0),
+        assert_eq!(*second.0.lock().unwrap(), (vec!["b".to_owned()], 1));
+        logger.with_logs(|logs| assert!(logs.is_empty()));
+
+        logger.shutdown();
+        assert_eq!(first.0.lock().unwrap().1, 2);
+    }
+
+    #[test]
+    fn sink_errors() {
+        struct Broken;
+
+        impl std::io::Write for Broken {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let sink = LogfmtSink::new(Broken);
+        let errors = sink.errors();
+        let logger = Logger::builder().sink(sink).build();
+        info!(logger, "a");
+        info!(logger, "b");
+        logger.flush();
+
+        assert_eq!(errors.get(), 2);
+        logger.with_logs(|logs| assert_eq!(logs.len(), 2));
+    }
+
+    #[test]
+    fn flush_waits_for_every_clone() {
+        let logger = Logger::new(None);
+        let other = logger.clone();
+        for i in 0..1000 {
+            info!(logger, "{}", i);
+            info!(other, "{}", i);
+        }
+
+        assert!(logger.flush_timeout(Duration::from_secs(10)));
+        logger.with_logs(|logs| {
+            assert_eq!(logs.len(), 2000);
+            assert_eq!(logs[1999].message(), "999");
+        });
+    }
+
+    #[test]
+    fn shutdown_drains_queue() {
+        let logger = Logger::new(None);
+        let other = logger.clone();
+        for i in 0..100 {
+            info!(logger, "{}", i);
+        }
+
+        logger.shutdown();
+        info!(other, "discarded");
+        other.flush();
+
+        logger.with_logs(|logs| {
+            assert_eq!(logs.len(), 100);
+            assert_eq!(logs[99].message(), "99");
+        });
+    }
+
+    #[test]
+    fn concurrent_shutdown_waits() {
+        let logger = Logger::new(None);
+        let gate = stall(&logger);
+        for i in 0..100 {
+            info!(logger, "{}", i);
+        }
+
+        let first = std::thread::spawn({
+            let logger = logger.clone();
+            move || logger.shutdown()
+        });
+        // give the first shutdown time to start waiting on the stalled thread
+        std::thread::sleep(Duration::from_millis(50));
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            std::mem::drop(gate);
+        });
+
+        logger.shutdown();
+        logger.with_logs(|logs| {
+            assert_eq!(logs.len(), 101);
+            assert_eq!(logs[100].message(), "99");
+        });
+
+        first.join().unwrap();
+        release.join().unwrap();
+    }
+
+    #[test]
+    fn drop_drains_queue() {
+        let logged = Arc::new(AtomicBool::new(false));
+        let logger = Logger::new(None);
+        logger.log(Level::Info, callsite!(), {
+            let logged = logged.clone();
+            move |writer: &mut dyn std::fmt::Write| {
+                logged.store(true, Ordering::Relaxed);
+                writer.write_str("done")
+            }
+        });
+
+        std::mem::drop(logger);
+        assert!(logged.load(Ordering::Relaxed));
+    }
+
+    /// Sends a log that blocks the backing thread until the returned sender is dropped.
+    fn stall(logger: &Logger) -> flume::Sender<()> {
+        let (started, wait_started) = flume::bounded(0);
+        let (gate, wait_gate) = flume::bounded::<()>(0);
+        logger.log(
+            Level::Info,
+            callsite!(),
+            move |writer: &mut dyn std::fmt::Write| {
+                started.send(()).unwrap();
+                let _ = wait_gate.recv();
+                writer.write_str("stalled")
+            },
         );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        $logger.log(
-            $crate::Level::Error,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1),
+
+        wait_started.recv().unwrap();
+        gate
+    }
+
+    #[test]
+    fn builder() {
+        let time = chrono::DateTime::from_timestamp(1_000_000, 0).unwrap();
+        let logger = Logger::builder()
+            .limit(3)
+            .thread_name("qlog-test")
+            .min_level(Level::Info)
+            .time_source(move || time)
+            .build();
+
+        logger.log(
+            Level::Info,
+            callsite!(),
+            |writer: &mut dyn std::fmt::Write| {
+                writer.write_str(std::thread::current().name().unwrap())
+            },
         );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr, $_2:expr) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        let _2 = $_2;
-        $logger.log(
-            $crate::Level::Error,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1, _2),
+        debug!(logger, "filtered");
+        warn!(logger, "a");
+        error!(logger, "b");
+        logger.flush();
+
+        logger.with_logs(|logs| {
+            assert_eq!(logs.len(), 3);
+            assert_eq!(logs[0].message(), "qlog-test");
+            assert_eq!(logs[1].message(), "a");
+            assert_eq!(logs[2].message(), "b");
+            assert_eq!(logs[2].time, time);
+        });
+    }
+
+    #[test]
+    fn runtime_min_level() {
+        let logger = Logger::new(None);
+        assert_eq!(logger.min_level(), Level::Trace);
+
+        logger.set_min_level(Level::Warn);
+        assert_eq!(logger.min_level(), Level::Warn);
+        assert!(!logger.enabled(Level::Info));
+        assert!(logger.enabled(Level::Error));
+
+        let mut evaluated = false;
+        info!(logger, "{}", {
+            evaluated = true;
+            0
+        });
+        warn!(logger, "kept");
+        assert!(!evaluated);
+
+        logger.set_min_level(Level::Debug);
+        debug!(logger, "also kept");
+        logger.flush();
+
+        logger.with_logs(|logs| {
+            assert_eq!(logs.len(), 2);
+            assert_eq!(logs[0].message(), "kept");
+            assert_eq!(logs[1].message(), "also kept");
+        });
+    }
+
+    #[test]
+    fn custom_levels() {
+        const VERBOSE: Level = Level::custom("Verbose", 15);
+
+        let logger = Logger::new(None);
+        // a custom level with a built-in severity is still told apart
+        logger.set_min_level(Level::custom("Notice", 30));
+        assert_eq!(logger.min_level(), Level::custom("Notice", 30));
+        assert!(logger.enabled(Level::Info));
+        assert!(!logger.enabled(Level::Debug));
+
+        logger.set_min_level(VERBOSE);
+        assert_eq!(logger.min_level(), VERBOSE);
+
+        trace!(logger, "filtered");
+        logger.log(VERBOSE, callsite!(), "verbose");
+        debug!(logger, "debug");
+        logger.flush();
+
+        logger.with_logs(|logs| {
+            assert_eq!(logs.len(), 2);
+            assert_eq!(logs[0].level, VERBOSE);
+            assert_eq!(logs[0].level.as_ref(), "Verbose");
+            assert_eq!(logs[1].level, Level::Debug);
+        });
+    }
+
+    #[test]
+    fn backpressure_drop_newest() {
+        let logger = Logger::builder()
+            .channel_capacity(16)
+            .backpressure(Backpressure::DropNewest)
+            .build();
+
+        let gate = stall(&logger);
+        for i in 0..26 {
+            info!(logger, "{}", i);
+        }
+
+        assert_eq!(logger.dropped(), 10);
+        std::mem::drop(gate);
+        logger.flush();
+
+        logger.with_logs(|logs| {
+            assert_eq!(logs.len(), 17);
+            assert_eq!(logs.last().unwrap().message(), "15");
+        });
+    }
+
+    #[test]
+    fn try_log() {
+        let logger = Logger::builder().channel_capacity(16).build();
+        let gate = stall(&logger);
+        for _ in 0..16 {
+            assert_eq!(logger.try_log(Level::Info, callsite!(), "queued"), Ok(()));
+        }
+
+        assert_eq!(
+            logger.try_log(Level::Info, callsite!(), "full"),
+            Err(LogError::QueueFull)
         );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr, $_2:expr, $_3:expr) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        let _2 = $_2;
-        let _3 = $_3;
-        $logger.log(
-            $crate::Level::Error,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1, _2, _3),
+        assert_eq!(logger.dropped(), 0);
+
+        std::mem::drop(gate);
+        logger.shutdown();
+        assert_eq!(
+            logger.try_log(Level::Info, callsite!(), "dead"),
+            Err(LogError::WorkerDead)
         );
-    }};
-    ($logger:expr, $s:literal, $_0:expr, $_1:expr, $_2:expr, $_3:expr, $_4:expr $(,)*) => {{
-        let _0 = $_0;
-        let _1 = $_1;
-        let _2 = $_2;
-        let _3 = $_3;
-        let _4 = $_4;
-        $logger.log(
-            $crate::Level::Error,
-            move |writer: &mut dyn ::std::fmt::Write| write!(writer, $s, _0, _1, _2, _3, _4),
+    }
+
+    #[test]
+    fn formatting_failure() {
+        let logger = Logger::new(None);
+        logger.log(
+            Level::Warn,
+            callsite!(),
+            |writer: &mut dyn std::fmt::Write| {
+                writer.write_str("partial")?;
+                Err(std::fmt::Error)
+            },
         );
-    }};
-}
+        info!(logger, "still alive");
+        logger.flush();
 
-#[cfg(test)]
-mod test {
-    use crate::Logger;
-    use std::{thread::sleep, time::Duration};
+        logger.with_logs(|logs| {
+            assert_eq!(logs[0].level, Level::Warn);
+            assert_eq!(logs[0].message(), format!("partial{FORMAT_ERROR}"));
+            assert_eq!(logs[1].message(), "still alive");
+        });
+    }
 
     #[test]
-    fn simple() {
+    fn deferred_formatting_panic() {
+        struct Panics;
+
+        impl std::fmt::Display for Panics {
+            fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                panic!("broken display");
+            }
+        }
+
+        let logger = Logger::builder().deferred_formatting(true).build();
+        info!(logger, "{}", Panics);
+        info!(logger, "still alive");
+        logger.flush();
+
+        logger.with_logs(|logs| {
+            assert_eq!(logs[0].message(), FORMAT_ERROR);
+            assert_eq!(logs[1].message(), "still alive");
+        });
+        info!(logger, "after");
+        logger.flush();
+        logger.with_logs(|logs| assert_eq!(logs[2].message(), "after"));
+    }
+
+    #[test]
+    fn backpressure_drop_oldest() {
+        let logger = Logger::builder()
+            .channel_capacity(16)
+            .backpressure(Backpressure::DropOldest)
+            .build();
+
+        let gate = stall(&logger);
+        for i in 0..26 {
+            info!(logger, "{}", i);
+        }
+
+        assert_eq!(logger.dropped(), 10);
+        std::mem::drop(gate);
+        logger.flush();
+
+        logger.with_logs(|logs| {
+            assert_eq!(logs.len(), 17);
+            assert_eq!(logs[1].message(), "10");
+            assert_eq!(logs.last().unwrap().message(), "25");
+        });
+    }
+
+    #[test]
+    fn drop_oldest_keeps_control_messages() {
+        let logger = Logger::builder()
+            .channel_capacity(1)
+            .backpressure(Backpressure::DropOldest)
+            .build();
+
+        // the queued sink can't be evicted, so the log has to make room for it again once sent
+        let gate = stall(&logger);
+        let sink = Collect::default();
+        logger.add_sink(sink.clone());
+        info!(logger, "evicted");
+
+        assert_eq!(logger.dropped(), 1);
+        std::mem::drop(gate);
+        logger.flush();
+        info!(logger, "kept");
+        logger.flush();
+
+        assert_eq!(sink.0.lock().unwrap().0, ["kept"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn export_jsonl() {
+        const FATAL: Level = Level::custom("Fatal", 60);
+
         let logger = Logger::new(None);
-        debug!(logger, "hello there: {}", 0);
+        warn!(logger, "bad opcode"; pc = Hex(0xBFC0_0000), op = 0x3F_u64);
+        logger.log(FATAL, callsite!(), "gpu hung");
+        logger.flush();
+
+        let mut out = Vec::new();
+        logger.export_jsonl(&mut out).unwrap();
+
+        let text = std::str::from_utf8(&out).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.contains(r#""level":"Warn""#));
+        assert!(text.contains(r#""value":{"hex":3217031168}"#));
+
+        let parsed = serde_json::Deserializer::from_slice(&out)
+            .into_iter::<Log>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        logger.with_logs(|logs| {
+            assert_eq!(parsed.len(), logs.len());
+            for (parsed, log) in parsed.iter().zip(logs) {
+                assert_eq!(parsed.time, log.time);
+                assert_eq!(parsed.level, log.level);
+                assert_eq!(parsed.target, log.target);
+                assert_eq!(parsed.location, log.location);
+                assert_eq!(parsed.message(), log.message());
+                assert_eq!(parsed.fields(), log.fields());
+            }
+        });
+        assert_eq!(parsed[1].level.name(), "Fatal");
+    }
+
+    #[test]
+    fn deferred_formatting() {
+        let formatted = Arc::new(AtomicUsize::new(0));
+        let logger = Logger::builder().limit(2).deferred_formatting(true).build();
+
+        for i in 0..4 {
+            let formatted = formatted.clone();
+            logger.log(
+                Level::Info,
+                callsite!(),
+                move |w: &mut dyn std::fmt::Write| {
+                    formatted.fetch_add(1, Ordering::Relaxed);
+                    write!(w, "{i}")
+                },
+            );
+        }
+        info!(logger, "count"; i = 4);
+        logger.flush();
+
+        // evicted logs are never formatted, and kept ones only when read
+        assert_eq!(formatted.load(Ordering::Relaxed), 0);
+        assert_eq!(Arc::strong_count(&formatted), 2);
+        logger.with_logs(|logs| {
+            assert_eq!(logs[0].message(), "3");
+            assert_eq!(logs[1].field("i"), Some(&Value::I64(4)));
+        });
+        assert_eq!(formatted.load(Ordering::Relaxed), 1);
+        assert_eq!(Arc::strong_count(&formatted), 1);
+
+        logger.with_logs(|logs| assert_eq!(logs[0].message(), "3"));
+        assert_eq!(formatted.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn interned() {
+        let logger = Logger::new(None);
+        for pc in [0xBFC0_0180u32, 0x8000_0080] {
+            trace_interned!(logger, "{:08X}: {} {:+.1} {:?}", pc, "lui", -1.25f32, 'x');
+        }
+        logger.log(Level::Info, callsite!(), "text");
+        logger.flush();
+
+        let mut writer = BinaryWriter::new(Vec::new()).unwrap();
+        let location = logger.with_logs(|logs| {
+            assert_eq!(logs[0].message(), "BFC00180: lui -1.2 'x'");
+            assert_eq!(logs[1].message(), "80000080: lui -1.2 'x'");
+            assert!(logs[0].fields().is_empty());
+            assert!(logs[0].packed().is_some());
+            assert!(logs[2].packed().is_none());
+
+            for log in logs {
+                writer.accept(log);
+            }
+            logs[0].location
+        });
+
+        let bytes = writer.into_inner().unwrap();
+        let read = BinaryReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(read[0].packed().is_some());
+        assert_eq!(read[0].location, location);
+        assert_eq!(read[1].message(), "80000080: lui -1.2 'x'");
+        assert_eq!(read[2].message(), "text");
+    }
+
+    #[test]
+    fn memory_budget() {
+        const BUDGET: usize = 8 * 1024;
+
+        let logger = Logger::builder().memory_budget(BUDGET).build();
+        for i in 0..200 {
+            info!(logger, "{i}"; i = i);
+        }
+        let dump = "ff".repeat(2000);
+        info!(logger, "{}", dump.clone());
+        // too big to ever fit, so it isn't kept
+        let huge = "ff".repeat(BUDGET);
+        info!(logger, "{huge}");
+        logger.flush();
+
+        let usage = logger.memory_usage();
+        assert!(usage > 4000 && usage <= BUDGET);
+        logger.with_logs(|logs| {
+            let len = logs.len();
+            assert!(len > 1 && len < 201);
+            assert_eq!(logs.last().unwrap().message(), dump);
+            // the oldest ones were evicted
+            for (log, i) in logs.iter().rev().skip(1).zip((0..200).rev()) {
+                assert_eq!(log.field("i"), Some(&Value::I64(i)));
+            }
+        });
+
+        logger.clear();
+        assert_eq!(logger.memory_usage(), 0);
+    }
+
+    #[test]
+    fn memory_budget_deferred() {
+        const BUDGET: usize = 16 * 1024;
+
+        let logger = Logger::builder()
+            .deferred_formatting(true)
+            .memory_budget(BUDGET)
+            .build();
+        let dump = "ff".repeat(500);
+        for i in 0..40 {
+            info!(logger, "{} {}", i, dump.clone());
+        }
+        logger.flush();
+
+        // unread logs are only charged for their overhead
+        let unread = logger.memory_usage();
+        assert!(unread < BUDGET);
+        logger.with_logs(|logs| assert_eq!(logs.len(), 40));
+
+        // reading formats them, which is charged, and the next log evicts the oldest ones
+        logger.with_logs(|logs| {
+            logs.iter()
+                .for_each(|log| assert!(log.message().ends_with("ff")))
+        });
+        assert!(logger.memory_usage() > unread + 40 * 1000);
+        info!(logger, "last");
+        logger.flush();
+
+        assert!(logger.memory_usage() <= BUDGET);
+        logger.with_logs(|logs| {
+            assert!(logs.len() < 20);
+            assert_eq!(logs.last().unwrap().message(), "last");
+            assert!(logs[0]
+                .message()
+                .starts_with(&format!("{} ", 40 - logs.len() + 1)));
+        });
+
+        logger.clear();
+        assert_eq!(logger.memory_usage(), 0);
+    }
+
+    // stricter features, and the release ones outside of debug builds, take precedence
+    #[cfg(all(
+        feature = "max_level_warn",
+        not(any(feature = "max_level_error", feature = "max_level_off")),
+        any(
+            debug_assertions,
+            not(any(
+                feature = "release_max_level_off",
+                feature = "release_max_level_error",
+                feature = "release_max_level_warn",
+                feature = "release_max_level_info",
+                feature = "release_max_level_debug",
+                feature = "release_max_level_trace",
+            ))
+        )
+    ))]
+    #[test]
+    fn max_level_warn() {
+        const _: () = assert!(!crate::statically_enabled(Level::Info));
+        const _: () = assert!(crate::statically_enabled(Level::Warn));
+
+        let logger = Logger::new(None);
+        assert!(!logger.enabled(Level::Debug));
 
-        sleep(Duration::from_secs_f32(0.1));
+        // stripped logs still have to type-check, but never evaluate their arguments
+        let evaluated = AtomicBool::new(false);
+        let evaluate = |value: u32| {
+            evaluated.store(true, Ordering::Relaxed);
+            value
+        };
+        trace!(logger, "{}", evaluate(0));
+        debug!(logger, "{:x}", evaluate(1); value = evaluate(2));
+        info!(logger, "{n}", n = evaluate(3));
+        info_once!(logger, "{}", evaluate(4));
+        warn!(logger, "kept {}", 5);
+        logger.flush();
 
+        assert!(!evaluated.load(Ordering::Relaxed));
         logger.with_logs(|logs| {
-            assert_eq!(logs[0].message, "hello there: 0");
+            assert_eq!(logs.len(), 1);
+            assert_eq!(logs[0].message(), "kept 5");
         });
     }
 }
@@ -0,0 +1,157 @@
+use crate::{Log, Value};
+use std::fmt::{self, Display, Write};
+
+/// A [`Log`] displayed in [logfmt](https://brandur.org/logfmt), as a single line of `key=value`
+/// pairs such as
+/// `time=2024-03-01T12:34:56.789Z level=warn target=emu::cpu location=src/cpu.rs:42 msg="unhandled DMA channel" channel=2`.
+/// Created by [`Log::logfmt`].
+///
+/// Values are quoted if they're empty or contain spaces, `=`, `"` or control characters, in which
+/// case `"`, `\` and control characters are escaped. Fields come after the built-in keys, followed
+/// by `repeat_count` if the log was repeated. Keys can't be quoted, so spaces, `=`, `"` and control
+/// characters in field names are replaced by `_`, and empty names are written as `_`.
+#[derive(Debug, Clone, Copy)]
+pub struct Logfmt<'a> {
+    pub(crate) log: &'a Log,
+}
+
+/// Whether `value` has to be quoted to be a logfmt value.
+fn needs_quotes(value: &str) -> bool {
+    value.is_empty()
+        || value
+            .chars()
+            .any(|c| c == ' ' || c == '=' || c == '"' || c.is_control())
+}
+
+/// Writes `value`, quoting and escaping it if needed.
+fn write_str(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    if !needs_quotes(value) {
+        return f.write_str(value);
+    }
+
+    f.write_char('"')?;
+    for c in value.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Writes `key`, replacing the characters which can't be part of a logfmt key.
+fn write_key(f: &mut fmt::Formatter<'_>, key: &str) -> fmt::Result {
+    if key.is_empty() {
+        return f.write_char('_');
+    }
+
+    key.chars().try_for_each(|c| match c {
+        ' ' | '=' | '"' => f.write_char('_'),
+        c if c.is_control() => f.write_char('_'),
+        c => f.write_char(c),
+    })
+}
+
+impl Display for Logfmt<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let log = self.log;
+
+        write!(f, "time={}", log.time.format("%Y-%m-%dT%H:%M:%S%.3fZ"))?;
+
+        f.write_str(" level=")?;
+        let level = log.level.name();
+        if level.chars().all(|c| c.is_ascii_alphanumeric()) {
+            level
+                .chars()
+                .try_for_each(|c| f.write_char(c.to_ascii_lowercase()))?;
+        } else {
+            write_str(f, &level.to_lowercase())?;
+        }
+
+        f.write_str(" target=")?;
+        write_str(f, log.target)?;
+        f.write_str(" location=")?;
+        write_str(f, &log.location.to_string())?;
+        f.write_str(" msg=")?;
+        write_str(f, log.message())?;
+
+        for field in log.fields() {
+            f.write_char(' ')?;
+            write_key(f, field.name)?;
+            f.write_char('=')?;
+            match &field.value {
+                Value::Str(value) => write_str(f, value)?,
+                value => write!(f, "{value}")?,
+            }
+        }
+
+        if log.repeat_count > 0 {
+            write!(f, " repeat_count={}", log.repeat_count)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{test::test_log, Field, Hex, Level};
+
+    #[test]
+    fn quoting() {
+        let log = test_log(
+            "unhandled DMA channel",
+            vec![
+                Field::new("channel", 2),
+                Field::new("addr", Hex(0x1F80_10F0)),
+            ],
+        );
+
+        assert_eq!(
+            log.logfmt().to_string(),
+            "time=2024-03-01T12:34:56.789Z level=warn target=emu::cpu location=src/cpu.rs:42 \
+             msg=\"unhandled DMA channel\" channel=2 addr=0x1F8010F0"
+        );
+
+        let mut log = test_log(
+            "said \"hi\"\n\tC:\\x=1\u{1}",
+            vec![
+                Field::new("empty", ""),
+                Field::new("plain", "ok"),
+                Field::new("ok", true),
+            ],
+        );
+        log.level = Level::custom("Very Bad", 60);
+        log.repeat_count = 3;
+
+        assert_eq!(
+            log.logfmt().to_string(),
+            "time=2024-03-01T12:34:56.789Z level=\"very bad\" target=emu::cpu \
+             location=src/cpu.rs:42 msg=\"said \\\"hi\\\"\\n\\tC:\\\\x=1\\u0001\" empty=\"\" \
+             plain=ok ok=true repeat_count=3"
+        );
+    }
+
+    #[test]
+    fn hostile_keys() {
+        let log = test_log(
+            "hi",
+            vec![
+                Field::new("bad key=\"x\"\n", 1),
+                Field::new("", 2),
+                Field::new("ünïcode", 3),
+            ],
+        );
+
+        assert_eq!(
+            log.logfmt().to_string(),
+            "time=2024-03-01T12:34:56.789Z level=warn target=emu::cpu location=src/cpu.rs:42 \
+             msg=hi bad_key__x__=1 _=2 ünïcode=3"
+        );
+    }
+}
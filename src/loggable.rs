@@ -1,9 +1,21 @@
+use crate::Field;
 use std::fmt::Write;
 
 /// Trait for types which can be logged.
 pub trait Loggable: Send {
     /// Logs this value to a given writer.
     fn log_to(self, writer: &mut dyn Write) -> std::fmt::Result;
+
+    /// Logs this value to a given writer, and records the structured [`Field`]s it carries into
+    /// `fields`. By default, no fields are recorded.
+    #[inline(always)]
+    fn log_with_fields(self, writer: &mut dyn Write, fields: &mut Vec<Field>) -> std::fmt::Result
+    where
+        Self: Sized,
+    {
+        let _ = fields;
+        self.log_to(writer)
+    }
 }
 
 impl<F> Loggable for F
@@ -16,27 +28,77 @@ where
     }
 }
 
-impl<'str> Loggable for &'str str {
+impl Loggable for &str {
     #[inline(always)]
     fn log_to(self, writer: &mut dyn Write) -> std::fmt::Result {
         writer.write_str(self)
     }
 }
 
+/// A [`Loggable`] which carries structured [`Field`]s, wrapping a function which writes the message
+/// and records the fields.
+pub struct Structured<F>(pub F);
+
+impl<F> Loggable for Structured<F>
+where
+    F: Send + FnOnce(&mut dyn Write, &mut Vec<Field>) -> std::fmt::Result,
+{
+    #[inline(always)]
+    fn log_to(self, writer: &mut dyn Write) -> std::fmt::Result {
+        self.log_with_fields(writer, &mut Vec::new())
+    }
+
+    #[inline(always)]
+    fn log_with_fields(self, writer: &mut dyn Write, fields: &mut Vec<Field>) -> std::fmt::Result {
+        (self.0)(writer, fields)
+    }
+}
+
 mod erased {
     use super::Loggable;
-    use std::{alloc::Layout, fmt::Write, mem::MaybeUninit};
+    use crate::Field;
+    use std::{
+        alloc::Layout,
+        fmt::Write,
+        mem::{ManuallyDrop, MaybeUninit},
+    };
+
+    /// Type-erased operations on a loggable. Both take a pointer to it and move out of it.
+    struct VTable {
+        log_to: unsafe fn(*const (), &mut dyn Write, &mut Vec<Field>) -> std::fmt::Result,
+        drop: unsafe fn(*const ()),
+    }
+
+    trait HasVTable {
+        const VTABLE: VTable;
+    }
+
+    impl<L> HasVTable for L
+    where
+        L: Loggable + 'static,
+    {
+        const VTABLE: VTable = VTable {
+            log_to: |value_ptr, writer, fields| {
+                let value = unsafe { value_ptr.cast::<L>().read_unaligned() };
+                value.log_with_fields(writer, fields)
+            },
+            drop: |value_ptr| {
+                std::mem::drop(unsafe { value_ptr.cast::<L>().read_unaligned() });
+            },
+        };
+    }
 
     const ERASED_SIZE: usize = 32;
     const INLINE_DATA_SIZE: usize = ERASED_SIZE - std::mem::size_of::<usize>();
 
+    #[derive(Clone, Copy)]
     enum Inner {
         Inline {
-            do_log_to: fn(*const (), &mut dyn Write) -> std::fmt::Result,
+            vtable: &'static VTable,
             data: MaybeUninit<[u8; INLINE_DATA_SIZE]>,
         },
         Boxed {
-            do_log_to: fn(*const (), &mut dyn Write) -> std::fmt::Result,
+            vtable: &'static VTable,
             layout: Layout,
             data: *mut (),
         },
@@ -50,15 +112,7 @@ mod erased {
         where
             L: Loggable + 'static,
         {
-            fn do_log_to<L>(value_ptr: *const L, writer: &mut dyn Write) -> std::fmt::Result
-            where
-                L: Loggable + 'static,
-            {
-                let value = unsafe { value_ptr.read_unaligned() };
-                value.log_to(writer)
-            }
-
-            let do_log_to = unsafe { std::mem::transmute(do_log_to::<L> as fn(_, _) -> _) };
+            let vtable = &<L as HasVTable>::VTABLE;
             if std::mem::size_of::<L>() > INLINE_DATA_SIZE {
                 let layout = Layout::new::<L>();
                 let data = unsafe { std::alloc::alloc(layout) };
@@ -67,15 +121,15 @@ mod erased {
                 unsafe { std::ptr::write_unaligned(data.cast(), value) };
 
                 Self::Boxed {
-                    do_log_to,
+                    vtable,
                     layout,
                     data: data.cast(),
                 }
             } else {
-                let mut data: MaybeUninit<[u8; ERASED_SIZE - 8]> = MaybeUninit::uninit();
+                let mut data: MaybeUninit<[u8; INLINE_DATA_SIZE]> = MaybeUninit::uninit();
                 unsafe { std::ptr::write_unaligned(data.as_mut_ptr().cast(), value) };
 
-                Self::Inline { do_log_to, data }
+                Self::Inline { vtable, data }
             }
         }
     }
@@ -95,13 +149,30 @@ mod erased {
     impl Loggable for ErasedLoggable {
         #[inline(always)]
         fn log_to(self, writer: &mut dyn Write) -> std::fmt::Result {
-            match self.0 {
-                Inner::Inline { do_log_to, data } => {
-                    do_log_to(std::ptr::addr_of!(data).cast(), writer)
-                }
+            self.log_with_fields(writer, &mut Vec::new())
+        }
+
+        #[inline(always)]
+        fn log_with_fields(
+            self,
+            writer: &mut dyn Write,
+            fields: &mut Vec<Field>,
+        ) -> std::fmt::Result {
+            // the value is moved out by `log_to`, so it must not be dropped again
+            let this = ManuallyDrop::new(self);
+            match this.0 {
+                Inner::Inline { vtable, data } => unsafe {
+                    (vtable.log_to)(std::ptr::addr_of!(data).cast(), writer, fields)
+                },
                 Inner::Boxed {
-                    do_log_to, data, ..
-                } => do_log_to(data, writer),
+                    vtable,
+                    layout,
+                    data,
+                } => {
+                    let result = unsafe { (vtable.log_to)(data, writer, fields) };
+                    unsafe { std::alloc::dealloc(data.cast(), layout) };
+                    result
+                }
             }
         }
     }
@@ -109,8 +180,18 @@ mod erased {
     impl Drop for ErasedLoggable {
         #[inline(always)]
         fn drop(&mut self) {
-            if let Inner::Boxed { layout, data, .. } = self.0 {
-                unsafe { std::alloc::dealloc(data.cast(), layout) };
+            match self.0 {
+                Inner::Inline { vtable, data } => unsafe {
+                    (vtable.drop)(std::ptr::addr_of!(data).cast())
+                },
+                Inner::Boxed {
+                    vtable,
+                    layout,
+                    data,
+                } => unsafe {
+                    (vtable.drop)(data);
+                    std::alloc::dealloc(data.cast(), layout);
+                },
             }
         }
     }
@@ -0,0 +1,482 @@
+use crate::{content::Content, Field, Log, Value};
+use std::{
+    collections::VecDeque,
+    ops::Index,
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// The minimum size of the arena once something is stored in it.
+const MIN_ARENA: usize = 4 * 1024;
+
+/// A circular buffer of bytes, which is allocated from and freed to in FIFO order.
+#[derive(Debug)]
+struct Arena {
+    /// Allocated as a `Box<[u8]>`, but kept as a pointer so that writing to one allocation doesn't
+    /// invalidate the `&str`s stored logs hold to the others.
+    buf: NonNull<[u8]>,
+    /// The start of the oldest allocation.
+    head: usize,
+    /// The end of the newest allocation.
+    tail: usize,
+    /// If allocations wrapped around to the start of the buffer, the end of the ones before that.
+    wrap: Option<usize>,
+}
+
+// SAFETY: the arena owns its buffer, like a `Box<[u8]>`
+unsafe impl Send for Arena {}
+unsafe impl Sync for Arena {}
+
+impl Default for Arena {
+    #[inline]
+    fn default() -> Self {
+        Self::from_box(Box::default())
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        // SAFETY: the buffer was allocated as a box, and nothing refers to it anymore
+        drop(unsafe { Box::from_raw(self.buf.as_ptr()) });
+    }
+}
+
+impl Arena {
+    #[inline]
+    fn from_box(buf: Box<[u8]>) -> Self {
+        Self {
+            buf: NonNull::from(Box::leak(buf)),
+            head: 0,
+            tail: 0,
+            wrap: None,
+        }
+    }
+
+    /// The size of the buffer.
+    #[inline]
+    fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// How many bytes are in use, including the ones skipped when wrapping around.
+    #[inline]
+    fn used(&self) -> usize {
+        match self.wrap {
+            Some(wrap) => wrap - self.head + self.tail,
+            None => self.tail - self.head,
+        }
+    }
+
+    /// Copies `bytes` into the arena, returning where they start. Fails if there's no contiguous
+    /// room for them.
+    fn alloc(&mut self, bytes: &[u8]) -> Option<usize> {
+        let len = bytes.len();
+        if len == 0 {
+            return Some(0);
+        }
+
+        let start = match self.wrap {
+            None if self.capacity() - self.tail >= len => self.tail,
+            None if self.head >= len => {
+                self.wrap = Some(self.tail);
+                0
+            }
+            Some(_) if self.head - self.tail >= len => self.tail,
+            _ => return None,
+        };
+
+        self.tail = start + len;
+        // SAFETY: `start..start + len` is in bounds and not part of any other allocation
+        unsafe {
+            let dst = self.buf.cast::<u8>().as_ptr().add(start);
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), dst, len);
+        }
+        Some(start)
+    }
+
+    /// Frees the oldest allocation.
+    fn free(&mut self, start: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+
+        debug_assert_eq!(start, self.head, "arena is freed in order");
+        self.head = start + len;
+        if self.wrap == Some(self.head) {
+            self.head = 0;
+            self.wrap = None;
+        }
+
+        // start over from the beginning once empty, to make the most of the contiguous room
+        if self.wrap.is_none() && self.head == self.tail {
+            self.head = 0;
+            self.tail = 0;
+        }
+    }
+
+    /// Where an allocation starting at `start` ends up in a grown arena.
+    #[inline]
+    fn relocate(&self, start: usize) -> usize {
+        match self.wrap {
+            Some(wrap) if start < self.head => wrap - self.head + start,
+            _ => start - self.head,
+        }
+    }
+
+    /// Moves the allocations into a bigger buffer with room for at least `extra` more bytes,
+    /// without wrapping around. Allocations have to be [relocated](Arena::relocate).
+    fn grow(&mut self, extra: usize) -> Self {
+        let used = self.used();
+        let len = (self.capacity() * 2).max(used + extra).max(MIN_ARENA);
+        let mut buf = vec![0; len].into_boxed_slice();
+
+        match self.wrap {
+            Some(wrap) => {
+                let top = wrap - self.head;
+                buf[..top].copy_from_slice(self.bytes(self.head, top));
+                buf[top..used].copy_from_slice(self.bytes(0, self.tail));
+            }
+            None => buf[..used].copy_from_slice(self.bytes(self.head, used)),
+        }
+
+        let mut arena = Self::from_box(buf);
+        arena.tail = used;
+        arena
+    }
+
+    #[inline]
+    fn bytes(&self, start: usize, len: usize) -> &[u8] {
+        assert!(start + len <= self.capacity(), "in bounds");
+
+        // SAFETY: the range is in bounds, and it's only written to while it isn't allocated
+        unsafe { std::slice::from_raw_parts(self.buf.cast::<u8>().as_ptr().add(start), len) }
+    }
+
+    #[inline]
+    fn str(&self, start: usize, len: usize) -> &str {
+        let bytes = self.bytes(start, len);
+        debug_assert!(std::str::from_utf8(bytes).is_ok());
+
+        // SAFETY: only whole strings are copied into the arena
+        unsafe { std::str::from_utf8_unchecked(bytes) }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// Its message is in the arena if it's formatted.
+    log: Log,
+    /// Where its message starts in the arena, if it's there.
+    start: Option<usize>,
+    /// See [`Logs::size_of`].
+    size: usize,
+}
+
+/// The in-memory history of a [`Logger`](crate::Logger), accessed through
+/// [`Logger::with_logs`](crate::Logger::with_logs). Logs are ordered from oldest to newest, and
+/// can be indexed like a slice.
+///
+/// Messages are stored back to back in a single circular buffer rather than in a `String` each,
+/// so storing a log doesn't allocate once the history is full.
+#[derive(Default)]
+pub struct Logs {
+    entries: VecDeque<Entry>,
+    arena: Arena,
+    /// The fields of the last evicted log, reused for the next one.
+    spare_fields: Vec<Field>,
+    /// The sum of the sizes of the logs.
+    usage: usize,
+    /// The sum of the sizes of the messages and fields of logs formatted after being stored, which
+    /// isn't part of their size.
+    formatted: Arc<AtomicUsize>,
+}
+
+impl Logs {
+    #[inline]
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            ..Default::default()
+        }
+    }
+
+    /// The number of logs.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no logs.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The log at `index`, where 0 is the oldest one.
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&Log> {
+        self.entries.get(index).map(|entry| &entry.log)
+    }
+
+    /// The oldest log.
+    #[inline]
+    pub fn first(&self) -> Option<&Log> {
+        self.entries.front().map(|entry| &entry.log)
+    }
+
+    /// The newest log.
+    #[inline]
+    pub fn last(&self) -> Option<&Log> {
+        self.entries.back().map(|entry| &entry.log)
+    }
+
+    /// Iterates over the logs, from oldest to newest.
+    #[inline]
+    pub fn iter(&self) -> LogsIter<'_> {
+        LogsIter {
+            entries: self.entries.iter(),
+        }
+    }
+
+    /// How much memory the logs take, in bytes: the sum of their [sizes](Logs::size_of), plus the
+    /// messages and fields of the ones formatted since they were stored.
+    #[inline]
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.usage + self.formatted.load(Ordering::Relaxed)
+    }
+
+    /// How much memory `log` takes once stored, in bytes: a fixed overhead, plus its message and
+    /// fields if they're formatted, plus the encoded arguments of interned logs. Logs which are
+    /// only formatted when read are charged for their message and fields once they are.
+    pub(crate) fn size_of(log: &Log) -> usize {
+        let packed = log
+            .packed()
+            .map_or(0, |packed| packed.args.as_bytes().len());
+
+        std::mem::size_of::<Entry>() + packed + log.content.formatted_size().unwrap_or(0)
+    }
+
+    /// Stores a copy of `log`.
+    pub(crate) fn push(&mut self, log: &Log) {
+        if log.content.is_lazy() {
+            return self.push_owned(log.clone());
+        }
+
+        let size = Self::size_of(log);
+        let start = self.store(log.message());
+
+        let mut fields = std::mem::take(&mut self.spare_fields);
+        fields.clear();
+        fields.extend_from_slice(log.fields());
+
+        let message = self.arena.str(start, log.message().len());
+        // SAFETY: the message is only freed from the arena when the log is removed, and it's
+        // pointed to its new place when the arena grows
+        let content = unsafe { Content::stored(message, fields) };
+        let mut stored = Log::with_content(log.time, log.level, log.target, log.location, content);
+        stored.last_time = log.last_time;
+        stored.repeat_count = log.repeat_count;
+
+        self.usage += size;
+        self.entries.push_back(Entry {
+            log: stored,
+            start: Some(start),
+            size,
+        });
+    }
+
+    /// Stores `log`, keeping its content as is if it isn't formatted yet.
+    pub(crate) fn push_owned(&mut self, mut log: Log) {
+        if !log.content.is_lazy() {
+            return self.push(&log);
+        }
+
+        let size = Self::size_of(&log);
+        log.content.charge_to(self.formatted.clone());
+        self.usage += size;
+        self.entries.push_back(Entry {
+            log,
+            start: None,
+            size,
+        });
+    }
+
+    /// Copies a formatted message into the arena, growing it if it's full, and returns where it
+    /// starts.
+    fn store(&mut self, message: &str) -> usize {
+        if let Some(start) = self.arena.alloc(message.as_bytes()) {
+            return start;
+        }
+
+        let arena = self.arena.grow(message.len());
+        for entry in &mut self.entries {
+            let Some(start) = &mut entry.start else {
+                continue;
+            };
+
+            let len = entry.log.message().len();
+            if len != 0 {
+                *start = self.arena.relocate(*start);
+            }
+
+            // SAFETY: the grown arena holds the same bytes at the relocated start, and it's kept
+            // until the log is removed
+            unsafe { entry.log.content.move_stored(arena.str(*start, len)) };
+        }
+
+        self.arena = arena;
+        self.arena
+            .alloc(message.as_bytes())
+            .expect("arena has room after growing")
+    }
+
+    /// Removes the oldest log.
+    pub(crate) fn pop_front(&mut self) {
+        let Some(Entry { log, start, size }) = self.entries.pop_front() else {
+            return;
+        };
+
+        self.usage -= size;
+        self.formatted
+            .fetch_sub(log.content.charged(), Ordering::Relaxed);
+        if let Some(start) = start {
+            self.arena.free(start, log.message().len());
+        }
+
+        if let Some(fields) = log.content.into_stored_fields() {
+            if fields.capacity() > self.spare_fields.capacity() {
+                self.spare_fields = fields;
+            }
+        }
+    }
+
+    /// Counts `log` as a repetition of the newest log if it is one, returning whether it was.
+    pub(crate) fn repeat_last(&mut self, log: &Log) -> bool {
+        let Some(Entry { log: last, .. }) = self.entries.back_mut() else {
+            return false;
+        };
+        if !last.is_repeated_by(log) {
+            return false;
+        }
+
+        last.repeat_count += 1;
+        last.last_time = log.time;
+        true
+    }
+
+    /// Removes every log and frees the memory used by them.
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// How much memory a formatted message and its fields take, in bytes.
+pub(crate) fn formatted_size(message: &str, fields: &[Field]) -> usize {
+    let fields = fields.iter().map(|field| match &field.value {
+        Value::Str(value) => std::mem::size_of::<Field>() + value.len(),
+        _ => std::mem::size_of::<Field>(),
+    });
+
+    message.len() + fields.sum::<usize>()
+}
+
+impl Index<usize> for Logs {
+    type Output = Log;
+
+    /// The log at `index`, where 0 is the oldest one. Panics if there's no such log.
+    #[inline]
+    fn index(&self, index: usize) -> &Log {
+        &self.entries[index].log
+    }
+}
+
+impl std::fmt::Debug for Logs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> IntoIterator for &'a Logs {
+    type Item = &'a Log;
+    type IntoIter = LogsIter<'a>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over [`Logs`], from oldest to newest. Created by [`Logs::iter`].
+#[derive(Debug, Clone)]
+pub struct LogsIter<'a> {
+    entries: std::collections::vec_deque::Iter<'a, Entry>,
+}
+
+impl<'a> Iterator for LogsIter<'a> {
+    type Item = &'a Log;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|entry| &entry.log)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for LogsIter<'_> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries.next_back().map(|entry| &entry.log)
+    }
+}
+
+impl ExactSizeIterator for LogsIter<'_> {}
+
+#[cfg(test)]
+mod test {
+    use super::Logs;
+    use crate::{test::test_log, Field};
+    use std::collections::VecDeque;
+
+    #[test]
+    fn arena_wraps_and_grows() {
+        let mut logs = Logs::default();
+        let mut expected = VecDeque::new();
+        let mut arena_len = 0;
+
+        for i in 0..20_000usize {
+            // uneven sizes, including empty messages, so that allocations wrap around at odd spots
+            let message = "x".repeat(i * 7919 % 97) + &i.to_string().repeat(i % 3);
+            let fields = (i % 4 == 0).then(|| vec![Field::new("i", i as u64)]);
+            logs.push(&test_log(&message, fields.clone().unwrap_or_default()));
+            expected.push_back((message, fields.unwrap_or_default()));
+
+            // the limit changes over time, so the arena has to grow while wrapped around
+            let limit = if i < 10_000 { 50 + i / 100 } else { 100 };
+            while logs.len() > limit {
+                logs.pop_front();
+                expected.pop_front();
+            }
+
+            assert_eq!(logs.len(), expected.len());
+            for (log, (message, fields)) in logs.iter().zip(&expected) {
+                assert_eq!(log.message(), message);
+                assert_eq!(log.fields(), fields);
+            }
+            assert_eq!(logs[logs.len() - 1].message(), expected.back().unwrap().0);
+
+            if i == 15_000 {
+                arena_len = logs.arena.capacity();
+            }
+        }
+
+        // no growth once the history is full
+        assert_eq!(logs.arena.capacity(), arena_len);
+    }
+}
@@ -0,0 +1,379 @@
+/// Logs a message with the given [`Level`](crate::Level), which can be any expression. Takes a
+/// logger, the level, a format string and its arguments, optionally followed by structured
+/// [fields](crate::Field) after a semicolon:
+///
+/// ```
+/// # use qlog::{log, Level, Logger};
+/// # let logger = Logger::new(None);
+/// # let (ch, addr, n) = (2, 0x1f80_1080u32, 1024);
+/// let level = if ch == 2 { Level::Info } else { Level::Debug };
+/// log!(logger, level, "dma {} done at {addr:08x}", ch, addr = addr; channel = ch, bytes = n);
+/// ```
+///
+/// Arguments are evaluated in order, but only if the level is enabled. The level-specific macros,
+/// such as [`info!`](crate::info!), are shorthands for this one.
+#[macro_export]
+macro_rules! log {
+    ($logger:expr, $level:expr, $fmt:literal $($rest:tt)*) => {{
+        let logger = &$logger;
+        let level = $level;
+        if $crate::statically_enabled(level) && logger.enabled(level) {
+            $crate::__log_args!(@args logger level $fmt [] $($rest)*)
+        }
+    }};
+}
+
+/// Captures the arguments of [`log!`] one by one, binding each to a fresh variable so that they're
+/// evaluated at the call site, and then logs them.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_args {
+    // done, without fields
+    (@args $logger:ident $level:ident $fmt:literal [$($captured:tt)*] $(,)?) => {
+        $crate::__log_args!(@log $logger $level $fmt [$($captured)*] [])
+    };
+    // done, with fields
+    (@args $logger:ident $level:ident $fmt:literal [$($captured:tt)*] $(,)? ; $($key:ident = $value:expr),* $(,)?) => {{
+        let ($($key,)*) = ($($value,)*);
+        $crate::__log_args!(@log $logger $level $fmt [$($captured)*] [$($key)*])
+    }};
+    // named argument
+    (@args $logger:ident $level:ident $fmt:literal [$($captured:tt)*] , $name:ident = $value:expr , $($rest:tt)*) => {{
+        let arg = $value;
+        $crate::__log_args!(@args $logger $level $fmt [$($captured)* $name = arg,] , $($rest)*)
+    }};
+    (@args $logger:ident $level:ident $fmt:literal [$($captured:tt)*] , $name:ident = $value:expr ; $($rest:tt)*) => {{
+        let arg = $value;
+        $crate::__log_args!(@args $logger $level $fmt [$($captured)* $name = arg,] ; $($rest)*)
+    }};
+    (@args $logger:ident $level:ident $fmt:literal [$($captured:tt)*] , $name:ident = $value:expr) => {{
+        let arg = $value;
+        $crate::__log_args!(@args $logger $level $fmt [$($captured)* $name = arg,])
+    }};
+    // positional argument
+    (@args $logger:ident $level:ident $fmt:literal [$($captured:tt)*] , $value:expr , $($rest:tt)*) => {{
+        let arg = $value;
+        $crate::__log_args!(@args $logger $level $fmt [$($captured)* arg,] , $($rest)*)
+    }};
+    (@args $logger:ident $level:ident $fmt:literal [$($captured:tt)*] , $value:expr ; $($rest:tt)*) => {{
+        let arg = $value;
+        $crate::__log_args!(@args $logger $level $fmt [$($captured)* arg,] ; $($rest)*)
+    }};
+    (@args $logger:ident $level:ident $fmt:literal [$($captured:tt)*] , $value:expr) => {{
+        let arg = $value;
+        $crate::__log_args!(@args $logger $level $fmt [$($captured)* arg,])
+    }};
+    (@log $logger:ident $level:ident $fmt:literal [$($captured:tt)*] [$($key:ident)*]) => {
+        $logger.log(
+            $level,
+            $crate::callsite!(),
+            $crate::Structured(
+                move |writer: &mut dyn ::std::fmt::Write, _fields: &mut ::std::vec::Vec<$crate::Field>| {
+                    $(_fields.push($crate::Field::new(::std::stringify!($key), $key));)*
+                    ::std::write!(writer, $fmt, $($captured)*)
+                },
+            ),
+        )
+    };
+}
+
+/// Logs a message with [`Level::Trace`](crate::Level::Trace). See [`log!`] for the syntax.
+#[macro_export]
+macro_rules! trace {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log!($logger, $crate::Level::Trace, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Debug`](crate::Level::Debug). See [`log!`] for the syntax.
+#[macro_export]
+macro_rules! debug {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log!($logger, $crate::Level::Debug, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Info`](crate::Level::Info). See [`log!`] for the syntax.
+#[macro_export]
+macro_rules! info {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log!($logger, $crate::Level::Info, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Warn`](crate::Level::Warn). See [`log!`] for the syntax.
+#[macro_export]
+macro_rules! warn {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log!($logger, $crate::Level::Warn, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Error`](crate::Level::Error). See [`log!`] for the syntax.
+#[macro_export]
+macro_rules! error {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log!($logger, $crate::Level::Error, $($arg)+)
+    };
+}
+
+/// Like [`log!`], but only logs the first time this call site is reached with the level enabled.
+#[macro_export]
+macro_rules! log_once {
+    ($logger:expr, $level:expr, $($arg:tt)+) => {{
+        static ONCE: $crate::CallsiteOnce = $crate::CallsiteOnce::new();
+
+        let logger = &$logger;
+        let level = $level;
+        if $crate::statically_enabled(level) && logger.enabled(level) && ONCE.first() {
+            $crate::log!(logger, level, $($arg)+)
+        }
+    }};
+}
+
+/// Like [`log!`], but only logs once every `n` times this call site is reached with the level
+/// enabled, starting with the first: `log_every_n!(logger, level, n, "format", args...)`.
+#[macro_export]
+macro_rules! log_every_n {
+    ($logger:expr, $level:expr, $n:expr, $($arg:tt)+) => {{
+        static EVERY_N: $crate::CallsiteEveryN = $crate::CallsiteEveryN::new();
+
+        let logger = &$logger;
+        let level = $level;
+        if $crate::statically_enabled(level) && logger.enabled(level) && EVERY_N.tick($n) {
+            $crate::log!(logger, level, $($arg)+)
+        }
+    }};
+}
+
+/// Like [`log!`], but logs at most once per `period` (a [`Duration`](std::time::Duration)) from
+/// this call site, starting with the first time it's reached with the level enabled:
+/// `log_every!(logger, level, period, "format", args...)`.
+#[macro_export]
+macro_rules! log_every {
+    ($logger:expr, $level:expr, $period:expr, $($arg:tt)+) => {{
+        static EVERY: $crate::CallsiteEvery = $crate::CallsiteEvery::new();
+
+        let logger = &$logger;
+        let level = $level;
+        if $crate::statically_enabled(level) && logger.enabled(level) && EVERY.elapsed($period) {
+            $crate::log!(logger, level, $($arg)+)
+        }
+    }};
+}
+
+/// Logs a message with [`Level::Trace`](crate::Level::Trace) only once. See [`log_once!`].
+#[macro_export]
+macro_rules! trace_once {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log_once!($logger, $crate::Level::Trace, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Trace`](crate::Level::Trace) once every `n` times. See
+/// [`log_every_n!`].
+#[macro_export]
+macro_rules! trace_every_n {
+    ($logger:expr, $n:expr, $($arg:tt)+) => {
+        $crate::log_every_n!($logger, $crate::Level::Trace, $n, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Trace`](crate::Level::Trace) at most once per period. See
+/// [`log_every!`].
+#[macro_export]
+macro_rules! trace_every {
+    ($logger:expr, $period:expr, $($arg:tt)+) => {
+        $crate::log_every!($logger, $crate::Level::Trace, $period, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Debug`](crate::Level::Debug) only once. See [`log_once!`].
+#[macro_export]
+macro_rules! debug_once {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log_once!($logger, $crate::Level::Debug, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Debug`](crate::Level::Debug) once every `n` times. See
+/// [`log_every_n!`].
+#[macro_export]
+macro_rules! debug_every_n {
+    ($logger:expr, $n:expr, $($arg:tt)+) => {
+        $crate::log_every_n!($logger, $crate::Level::Debug, $n, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Debug`](crate::Level::Debug) at most once per period. See
+/// [`log_every!`].
+#[macro_export]
+macro_rules! debug_every {
+    ($logger:expr, $period:expr, $($arg:tt)+) => {
+        $crate::log_every!($logger, $crate::Level::Debug, $period, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Info`](crate::Level::Info) only once. See [`log_once!`].
+#[macro_export]
+macro_rules! info_once {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log_once!($logger, $crate::Level::Info, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Info`](crate::Level::Info) once every `n` times. See
+/// [`log_every_n!`].
+#[macro_export]
+macro_rules! info_every_n {
+    ($logger:expr, $n:expr, $($arg:tt)+) => {
+        $crate::log_every_n!($logger, $crate::Level::Info, $n, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Info`](crate::Level::Info) at most once per period. See
+/// [`log_every!`].
+#[macro_export]
+macro_rules! info_every {
+    ($logger:expr, $period:expr, $($arg:tt)+) => {
+        $crate::log_every!($logger, $crate::Level::Info, $period, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Warn`](crate::Level::Warn) only once. See [`log_once!`].
+#[macro_export]
+macro_rules! warn_once {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log_once!($logger, $crate::Level::Warn, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Warn`](crate::Level::Warn) once every `n` times. See
+/// [`log_every_n!`].
+#[macro_export]
+macro_rules! warn_every_n {
+    ($logger:expr, $n:expr, $($arg:tt)+) => {
+        $crate::log_every_n!($logger, $crate::Level::Warn, $n, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Warn`](crate::Level::Warn) at most once per period. See
+/// [`log_every!`].
+#[macro_export]
+macro_rules! warn_every {
+    ($logger:expr, $period:expr, $($arg:tt)+) => {
+        $crate::log_every!($logger, $crate::Level::Warn, $period, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Error`](crate::Level::Error) only once. See [`log_once!`].
+#[macro_export]
+macro_rules! error_once {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log_once!($logger, $crate::Level::Error, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Error`](crate::Level::Error) once every `n` times. See
+/// [`log_every_n!`].
+#[macro_export]
+macro_rules! error_every_n {
+    ($logger:expr, $n:expr, $($arg:tt)+) => {
+        $crate::log_every_n!($logger, $crate::Level::Error, $n, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Error`](crate::Level::Error) at most once per period. See
+/// [`log_every!`].
+#[macro_export]
+macro_rules! error_every {
+    ($logger:expr, $period:expr, $($arg:tt)+) => {
+        $crate::log_every!($logger, $crate::Level::Error, $period, $($arg)+)
+    };
+}
+
+/// Like [`log!`], but with an interned format string and binary-encoded arguments, for hot paths.
+/// The format string is registered once in a global table, and logs only carry its id and their
+/// arguments encoded as a handful of bytes. The message is only formatted when read, and
+/// [`BinaryWriter`](crate::BinaryWriter) persists logs in this form.
+///
+/// ```
+/// # use qlog::{log_interned, Level, Logger};
+/// # let logger = Logger::new(None);
+/// # let (pc, op) = (0xBFC0_0180u32, 0x3C08_BFC0u32);
+/// log_interned!(logger, Level::Trace, "{:08X}: {:#010x} ({})", pc, op, "lui");
+/// ```
+///
+/// Arguments must implement [`InternedArg`](crate::InternedArg), i.e. be integers, floats,
+/// `bool`, `char` or strings, and are evaluated in order, but only if the level is enabled. Format
+/// strings can only use implicitly positioned arguments (`{}`, `{:x}`, ...) with the `?`, `x`,
+/// `X`, `b` and `o` formatting traits, and there are no structured fields. Anything else is
+/// rejected at compile time:
+///
+/// ```compile_fail
+/// # use qlog::{log_interned, Level, Logger};
+/// # let logger = Logger::new(None);
+/// # let pc = 0xBFC0_0180u32;
+/// log_interned!(logger, Level::Trace, "{pc:08X}");
+/// ```
+#[macro_export]
+macro_rules! log_interned {
+    ($logger:expr, $level:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {{
+        let logger = &$logger;
+        let level = $level;
+        if $crate::statically_enabled(level) && logger.enabled(level) {
+            static FORMAT: $crate::InternedFormat = $crate::InternedFormat::new($fmt);
+
+            let mut args = $crate::ArgEncoder::new();
+            $($crate::InternedArg::encode(&$arg, &mut args);)*
+
+            // never runs, but makes the compiler check the format string against the arguments
+            #[allow(unreachable_code)]
+            if false {
+                let _ = ::std::format_args!($fmt $(, $arg)*);
+            }
+
+            logger.log_interned(level, $crate::callsite!(), &FORMAT, args)
+        }
+    }};
+}
+
+/// Logs an interned message with [`Level::Trace`](crate::Level::Trace). See [`log_interned!`].
+#[macro_export]
+macro_rules! trace_interned {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log_interned!($logger, $crate::Level::Trace, $($arg)+)
+    };
+}
+
+/// Logs an interned message with [`Level::Debug`](crate::Level::Debug). See [`log_interned!`].
+#[macro_export]
+macro_rules! debug_interned {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log_interned!($logger, $crate::Level::Debug, $($arg)+)
+    };
+}
+
+/// Logs an interned message with [`Level::Info`](crate::Level::Info). See [`log_interned!`].
+#[macro_export]
+macro_rules! info_interned {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log_interned!($logger, $crate::Level::Info, $($arg)+)
+    };
+}
+
+/// Logs an interned message with [`Level::Warn`](crate::Level::Warn). See [`log_interned!`].
+#[macro_export]
+macro_rules! warn_interned {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log_interned!($logger, $crate::Level::Warn, $($arg)+)
+    };
+}
+
+/// Logs an interned message with [`Level::Error`](crate::Level::Error). See [`log_interned!`].
+#[macro_export]
+macro_rules! error_interned {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::log_interned!($logger, $crate::Level::Error, $($arg)+)
+    };
+}
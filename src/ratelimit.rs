@@ -0,0 +1,88 @@
+//! Per-call site state for the rate-limited logging macros, such as [`log_once!`].
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// State for [`log_once!`].
+#[doc(hidden)]
+#[derive(Default)]
+pub struct CallsiteOnce(AtomicBool);
+
+impl CallsiteOnce {
+    #[inline]
+    pub const fn new() -> Self {
+        Self(AtomicBool::new(false))
+    }
+
+    /// Whether this is the first time it's been called.
+    #[inline]
+    pub fn first(&self) -> bool {
+        // avoid writing to the shared cache line unless needed
+        !self.0.load(Ordering::Relaxed) && !self.0.swap(true, Ordering::Relaxed)
+    }
+}
+
+/// State for [`log_every_n!`].
+#[doc(hidden)]
+#[derive(Default)]
+pub struct CallsiteEveryN(AtomicU64);
+
+impl CallsiteEveryN {
+    #[inline]
+    pub const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    /// Whether this is the first call, or the first call after `n` others.
+    #[inline]
+    // `u64::is_multiple_of` needs Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn tick(&self, n: u64) -> bool {
+        self.0.fetch_add(1, Ordering::Relaxed) % n.max(1) == 0
+    }
+}
+
+/// State for [`log_every!`].
+#[doc(hidden)]
+pub struct CallsiteEvery(AtomicU64);
+
+impl Default for CallsiteEvery {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CallsiteEvery {
+    /// Marks the state as never having elapsed.
+    const NEVER: u64 = u64::MAX;
+
+    #[inline]
+    pub const fn new() -> Self {
+        Self(AtomicU64::new(Self::NEVER))
+    }
+
+    /// Whether this is the first call, or the first call after `period` has elapsed since the last
+    /// one which returned `true`.
+    #[inline]
+    pub fn elapsed(&self, period: Duration) -> bool {
+        static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+        let epoch = *EPOCH.get_or_init(Instant::now);
+        let now = epoch.elapsed().as_nanos() as u64;
+        let last = self.0.load(Ordering::Relaxed);
+        if last != Self::NEVER && now.saturating_sub(last) < period.as_nanos() as u64 {
+            return false;
+        }
+
+        // if another thread raced us, it gets to log instead
+        self.0
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
+}
@@ -0,0 +1,162 @@
+//! `serde` implementations which can't be derived. Types holding `&'static str`s can't derive
+//! `Deserialize` without requiring the input itself to be `'static`, so they're deserialized into
+//! owned mirrors of themselves and their strings are interned. [`Log`] is serialized through a
+//! borrowed mirror, since its message might have to be formatted first.
+
+use crate::{intern::intern, Field, Level, Location, Log, Value};
+use chrono::{DateTime, Utc};
+use serde::{ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Built-in levels are serialized as their name, and custom ones as a struct with their name and
+/// severity.
+impl Serialize for Level {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Custom(custom) => {
+                let mut s = serializer.serialize_struct("CustomLevel", 2)?;
+                s.serialize_field("name", custom.name())?;
+                s.serialize_field("severity", &custom.severity())?;
+                s.end()
+            }
+            _ => serializer.serialize_str(self.name()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LevelRepr {
+    Builtin(String),
+    Custom { name: String, severity: u8 },
+}
+
+impl<'de> Deserialize<'de> for Level {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match LevelRepr::deserialize(deserializer)? {
+            LevelRepr::Builtin(name) => [
+                Self::Trace,
+                Self::Debug,
+                Self::Info,
+                Self::Warn,
+                Self::Error,
+            ]
+            .into_iter()
+            .find(|level| level.name() == name)
+            .ok_or_else(|| serde::de::Error::custom(format_args!("unknown level `{name}`"))),
+            LevelRepr::Custom { name, severity } => Ok(Self::custom(intern(&name), severity)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Location")]
+struct LocationRepr {
+    file: String,
+    line: u32,
+}
+
+impl<'de> Deserialize<'de> for Location {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let repr = LocationRepr::deserialize(deserializer)?;
+        Ok(Self {
+            file: intern(&repr.file),
+            line: repr.line,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Field")]
+struct FieldRepr {
+    name: String,
+    value: Value,
+}
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let repr = FieldRepr::deserialize(deserializer)?;
+        Ok(Self {
+            name: intern(&repr.name),
+            value: repr.value,
+        })
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "Log")]
+struct LogRef<'a> {
+    time: DateTime<Utc>,
+    last_time: DateTime<Utc>,
+    repeat_count: u32,
+    level: Level,
+    target: &'a str,
+    location: Location,
+    message: &'a str,
+    fields: &'a [Field],
+}
+
+impl Serialize for Log {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        LogRef {
+            time: self.time,
+            last_time: self.last_time,
+            repeat_count: self.repeat_count,
+            level: self.level,
+            target: self.target,
+            location: self.location,
+            message: self.message(),
+            fields: self.fields(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Log")]
+struct LogRepr {
+    time: DateTime<Utc>,
+    last_time: DateTime<Utc>,
+    repeat_count: u32,
+    level: Level,
+    target: String,
+    location: Location,
+    message: String,
+    fields: Vec<Field>,
+}
+
+/// Names are interned, see [reading logs back](Log#reading-logs-back).
+impl<'de> Deserialize<'de> for Log {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let repr = LogRepr::deserialize(deserializer)?;
+        let mut log = Log::new(
+            repr.time,
+            repr.level,
+            intern(&repr.target),
+            repr.location,
+            repr.message,
+            repr.fields,
+        );
+        log.last_time = repr.last_time;
+        log.repeat_count = repr.repeat_count;
+
+        Ok(log)
+    }
+}
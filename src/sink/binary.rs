@@ -0,0 +1,148 @@
+use super::{ErrorCount, Sink};
+use crate::{
+    binary::{encode_header, Encoder},
+    Log,
+};
+use std::io::{self, Write};
+
+/// A [`Sink`] which writes logs in a compact binary format, which can be read back with a
+/// [`BinaryReader`](crate::BinaryReader). This is much faster and smaller than text for high
+/// volumes of logs, such as instruction traces.
+///
+/// The format is versioned, and streams start with a header holding metadata given when creating
+/// the writer. See the documentation of the `binary` module in the source for its details.
+///
+/// Logs are buffered and written in batches, so the writer doesn't need to be buffered. Batches
+/// are written out when the sink is [flushed](Sink::flush), which the logger does when it's
+/// flushed or shut down, and when the writer is dropped. If the underlying writer fails, what
+/// couldn't be written is kept and retried with the next batch, and new logs are discarded while
+/// too much is pending, so that the stream never ends up with a partial record.
+pub struct BinaryWriter<W>
+where
+    W: Write,
+{
+    /// Only taken by [`BinaryWriter::into_inner`].
+    writer: Option<W>,
+    errors: ErrorCount,
+    encoder: Encoder,
+    buf: Vec<u8>,
+    scratch: Vec<u8>,
+}
+
+/// How many bytes are buffered before writing them out.
+const BATCH: usize = 64 * 1024;
+
+/// How many bytes can be left pending by failed writes before new logs are discarded.
+const MAX_PENDING: usize = 16 * BATCH;
+
+impl<W> BinaryWriter<W>
+where
+    W: Write,
+{
+    /// Creates a writer, writing the header of the stream with no metadata.
+    #[inline]
+    pub fn new(writer: W) -> io::Result<Self> {
+        Self::with_metadata(writer, &[])
+    }
+
+    /// Creates a writer, writing the header of the stream with the given metadata as key-value
+    /// pairs, such as the name and version of the program.
+    pub fn with_metadata(mut writer: W, metadata: &[(&str, &str)]) -> io::Result<Self> {
+        let mut buf = Vec::new();
+        encode_header(&mut buf, metadata);
+        writer.write_all(&buf)?;
+        buf.clear();
+
+        Ok(Self {
+            writer: Some(writer),
+            errors: ErrorCount::default(),
+            encoder: Encoder::default(),
+            buf,
+            scratch: Vec::new(),
+        })
+    }
+
+    /// The I/O errors hit by this sink. See [`ErrorCount`].
+    #[inline]
+    pub fn errors(&self) -> ErrorCount {
+        self.errors.clone()
+    }
+
+    fn writer(&mut self) -> &mut W {
+        self.writer
+            .as_mut()
+            .expect("writer is only taken when consumed")
+    }
+
+    /// Writes out the buffered logs. If that fails, only what was written is removed from the
+    /// buffer, so that the rest can be retried.
+    fn write_buffered(&mut self) -> io::Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("writer is only taken when consumed");
+        let mut written = 0;
+        let result = loop {
+            if written == self.buf.len() {
+                break Ok(());
+            }
+
+            match writer.write(&self.buf[written..]) {
+                Ok(0) => break Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.buf.drain(..written);
+        result
+    }
+
+    /// Writes out any buffered logs and consumes the writer, returning the underlying one.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.write_buffered()?;
+        self.writer().flush()?;
+
+        Ok(self
+            .writer
+            .take()
+            .expect("writer is only taken when consumed"))
+    }
+}
+
+impl<W> Drop for BinaryWriter<W>
+where
+    W: Write,
+{
+    fn drop(&mut self) {
+        // like `BufWriter`, errors can't be reported when dropping: they're only counted
+        if self.writer.is_some() {
+            let result = self.write_buffered();
+            let _ = self.errors.track(result);
+        }
+    }
+}
+
+impl<W> Sink for BinaryWriter<W>
+where
+    W: Write + Send,
+{
+    fn accept(&mut self, log: &Log) {
+        // the encoder remembers what it wrote, such as interned formats, so encoded logs can't be
+        // discarded afterwards without corrupting the stream
+        if self.buf.len() < MAX_PENDING {
+            self.encoder.encode(&mut self.buf, &mut self.scratch, log);
+        }
+
+        if self.buf.len() >= BATCH {
+            let result = self.write_buffered();
+            let _ = self.errors.track(result);
+        }
+    }
+
+    fn flush(&mut self) {
+        let result = self.write_buffered().and_then(|()| self.writer().flush());
+        let _ = self.errors.track(result);
+    }
+}
@@ -0,0 +1,263 @@
+use super::{ErrorCount, Sink};
+use crate::{format::write_line, Format, Level, Log};
+use std::{
+    ffi::OsString,
+    io::{self, IsTerminal, Stderr, Stdout, Write},
+};
+
+/// A [`Sink`] which prints logs to the console as lines of text, in the default [`Format`] unless
+/// another one is given with [`ConsoleSink::format`]. Logs at [`Level::Warn`] or above go to
+/// stderr, the rest to stdout.
+///
+/// Logs are colored by level using ANSI escape codes, unless the stream they're printed to isn't a
+/// terminal or the `NO_COLOR` environment variable is set (see <https://no-color.org>). This can
+/// be overridden with [`ConsoleSink::color`].
+///
+/// Other writers can stand in for stdout and stderr with [`ConsoleSink::with_writers`].
+#[derive(Debug)]
+pub struct ConsoleSink<O = Stdout, E = Stderr> {
+    stdout: O,
+    stderr: E,
+    stdout_color: bool,
+    stderr_color: bool,
+    format: Format,
+    line: String,
+    errors: ErrorCount,
+}
+
+impl ConsoleSink {
+    /// Creates a console sink, detecting whether colors should be used.
+    pub fn new() -> Self {
+        Self::detect(
+            |name| std::env::var_os(name),
+            io::stdout().is_terminal(),
+            io::stderr().is_terminal(),
+        )
+    }
+
+    /// Creates a console sink printing to stdout and stderr, with colors enabled for the streams
+    /// which are terminals unless `NO_COLOR` is set to a non-empty value according to `env`.
+    fn detect(
+        env: impl Fn(&str) -> Option<OsString>,
+        stdout_is_terminal: bool,
+        stderr_is_terminal: bool,
+    ) -> Self {
+        let no_color = env("NO_COLOR").is_some_and(|value| !value.is_empty());
+
+        Self {
+            stdout_color: !no_color && stdout_is_terminal,
+            stderr_color: !no_color && stderr_is_terminal,
+            ..Self::with_writers(io::stdout(), io::stderr())
+        }
+    }
+}
+
+impl<O, E> ConsoleSink<O, E> {
+    /// Creates a console sink printing to the given writers instead of stdout and stderr, such as
+    /// to capture its output. Colors are disabled unless enabled with [`ConsoleSink::color`].
+    pub fn with_writers(stdout: O, stderr: E) -> Self {
+        Self {
+            stdout,
+            stderr,
+            stdout_color: false,
+            stderr_color: false,
+            format: Format::default(),
+            line: String::new(),
+            errors: ErrorCount::default(),
+        }
+    }
+
+    /// Sets whether logs are colored, regardless of where they're printed to.
+    #[inline]
+    pub fn color(mut self, color: bool) -> Self {
+        self.stdout_color = color;
+        self.stderr_color = color;
+        self
+    }
+
+    /// Sets the [`Format`] logs are printed in.
+    #[inline]
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// The I/O errors hit by this sink. See [`ErrorCount`].
+    #[inline]
+    pub fn errors(&self) -> ErrorCount {
+        self.errors.clone()
+    }
+}
+
+impl Default for ConsoleSink {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for ConsoleSink {
+    fn clone(&self) -> Self {
+        Self {
+            stdout: io::stdout(),
+            stderr: io::stderr(),
+            stdout_color: self.stdout_color,
+            stderr_color: self.stderr_color,
+            format: self.format.clone(),
+            line: String::new(),
+            errors: self.errors.clone(),
+        }
+    }
+}
+
+/// The ANSI escape code for the color of a level. Custom levels take the color of the closest
+/// built-in level below them.
+fn color(level: Level) -> &'static str {
+    match level.severity() {
+        severity if severity >= Level::Error.severity() => "\x1b[31m",
+        severity if severity >= Level::Warn.severity() => "\x1b[33m",
+        severity if severity >= Level::Info.severity() => "\x1b[32m",
+        severity if severity >= Level::Debug.severity() => "\x1b[34m",
+        _ => "\x1b[90m",
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+
+/// Prints `line`, colored if needed. It's written with a single call, as stdout and stderr are
+/// only locked for the duration of each call and other threads could print in between.
+fn print(out: &mut impl Write, line: &mut String, level: Level, colored: bool) -> io::Result<()> {
+    if colored {
+        // keep the newline out of the colored part
+        let end = line.strip_suffix('\n').map_or(line.len(), str::len);
+        line.insert_str(end, RESET);
+        line.insert_str(0, color(level));
+    }
+
+    out.write_all(line.as_bytes())
+}
+
+impl<O, E> Sink for ConsoleSink<O, E>
+where
+    O: Write + Send,
+    E: Write + Send,
+{
+    fn accept(&mut self, log: &Log) {
+        write_line(&mut self.line, log, &self.format);
+
+        let result = if log.level >= Level::Warn {
+            print(
+                &mut self.stderr,
+                &mut self.line,
+                log.level,
+                self.stderr_color,
+            )
+        } else {
+            print(
+                &mut self.stdout,
+                &mut self.line,
+                log.level,
+                self.stdout_color,
+            )
+        };
+        let _ = self.errors.track(result);
+    }
+
+    fn flush(&mut self) {
+        let _ = self.errors.track(self.stdout.flush());
+        let _ = self.errors.track(self.stderr.flush());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{color, ConsoleSink, RESET};
+    use crate::{test::test_log, Level, Sink};
+    use std::{
+        ffi::OsString,
+        io::{self, Write},
+        sync::{Arc, Mutex},
+    };
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn capture(color: bool) -> (ConsoleSink<Buffer, Buffer>, Buffer, Buffer) {
+        let (stdout, stderr) = (Buffer::default(), Buffer::default());
+        let sink = ConsoleSink::with_writers(stdout.clone(), stderr.clone()).color(color);
+        (sink, stdout, stderr)
+    }
+
+    fn text(buffer: &Buffer) -> String {
+        String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    fn routes_by_level() {
+        let (mut sink, stdout, stderr) = capture(false);
+        for (level, message) in [
+            (Level::Trace, "a"),
+            (Level::Info, "b"),
+            (Level::Warn, "c"),
+            (Level::Error, "d"),
+            (Level::custom("Fatal", 60), "e"),
+        ] {
+            sink.accept(&test_log(message, Vec::new()).with_level(level));
+        }
+
+        let stdout = text(&stdout);
+        let stderr = text(&stderr);
+        assert_eq!(stdout.lines().count(), 2);
+        assert!(stdout.contains("] a\n") && stdout.contains("] b\n"));
+        assert_eq!(stderr.lines().count(), 3);
+        assert!(stderr.contains("] c\n") && stderr.contains("] d\n") && stderr.contains("] e\n"));
+        assert!(!stdout.contains('\x1b') && !stderr.contains('\x1b'));
+    }
+
+    #[test]
+    fn colors() {
+        let (mut sink, stdout, _) = capture(true);
+        sink.accept(&test_log("hi", Vec::new()).with_level(Level::Info));
+
+        let stdout = text(&stdout);
+        assert!(stdout.starts_with(color(Level::Info)));
+        assert!(stdout.ends_with(&format!("hi{RESET}\n")));
+    }
+
+    #[test]
+    fn color_detection() {
+        let env = |value: Option<&'static str>| move |_: &str| value.map(OsString::from);
+
+        let sink = ConsoleSink::detect(env(None), true, false);
+        assert!(sink.stdout_color && !sink.stderr_color);
+        let sink = ConsoleSink::detect(env(None), false, true);
+        assert!(!sink.stdout_color && sink.stderr_color);
+
+        // an empty NO_COLOR doesn't count
+        let sink = ConsoleSink::detect(env(Some("")), true, true);
+        assert!(sink.stdout_color && sink.stderr_color);
+        let sink = ConsoleSink::detect(env(Some("1")), true, true);
+        assert!(!sink.stdout_color && !sink.stderr_color);
+
+        // explicitly enabling colors overrides the detection
+        let sink = ConsoleSink::detect(env(Some("1")), false, false).color(true);
+        assert!(sink.stdout_color && sink.stderr_color);
+    }
+
+    #[test]
+    fn custom_level_colors() {
+        assert_eq!(color(Level::custom("Verbose", 15)), color(Level::Trace));
+        assert_eq!(color(Level::custom("Notice", 35)), color(Level::Info));
+        assert_eq!(color(Level::custom("Fatal", 60)), color(Level::Error));
+    }
+}
@@ -0,0 +1,268 @@
+use super::{ErrorCount, Sink};
+use crate::{format::write_line, Format, Log};
+use chrono::{DateTime, Utc};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// When a [`FileSink`] starts a new file, regardless of its size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    /// Never, only rotate based on size.
+    #[default]
+    Never,
+    /// Whenever a log is written in a different hour (in UTC) than the previous one.
+    Hourly,
+    /// Whenever a log is written in a different day (in UTC) than the previous one.
+    Daily,
+}
+
+impl Rotation {
+    /// Identifies the period a given time falls in. Times in the same period have the same key.
+    fn period(self, time: DateTime<Utc>) -> Option<i64> {
+        match self {
+            Self::Never => None,
+            Self::Hourly => Some(time.timestamp().div_euclid(3600)),
+            Self::Daily => Some(time.timestamp().div_euclid(86400)),
+        }
+    }
+}
+
+/// A [`Sink`] which writes logs to a file as lines of text, in the default [`Format`] unless
+/// another one is given with [`FileSink::format`].
+///
+/// The file can be rotated when it grows past a given size (see [`FileSink::max_size`]) or when
+/// a time boundary is crossed (see [`FileSink::rotation`]). When rotating, the current file is
+/// renamed to `<path>.1`, the previous `<path>.1` to `<path>.2` and so on, keeping only the last
+/// few archives (see [`FileSink::keep`]), and a new file is started.
+///
+/// If the file can't be written to, logs are discarded and it's reopened on the next one.
+pub struct FileSink {
+    path: PathBuf,
+    file: Option<BufWriter<File>>,
+    /// The size of the current file.
+    size: u64,
+    /// The rotation period of the last log written to the current file.
+    period: Option<i64>,
+    max_size: Option<u64>,
+    rotation: Rotation,
+    keep: usize,
+    format: Format,
+    line: String,
+    errors: ErrorCount,
+}
+
+impl FileSink {
+    /// Creates a sink which appends to the file at `path`, creating it if needed. By default, the
+    /// file is never rotated.
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut sink = Self {
+            path: path.as_ref().to_owned(),
+            file: None,
+            size: 0,
+            period: None,
+            max_size: None,
+            rotation: Rotation::Never,
+            keep: 5,
+            format: Format::default(),
+            line: String::new(),
+            errors: ErrorCount::default(),
+        };
+
+        sink.open()?;
+        Ok(sink)
+    }
+
+    /// Rotates the file before it grows past `max_size` bytes. A single line longer than that
+    /// still gets written, to a file of its own.
+    #[inline]
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Rotates the file when a log is written in a different period than the previous one.
+    #[inline]
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self.period = self.modified().and_then(|time| rotation.period(time));
+        self
+    }
+
+    /// Sets how many rotated files are kept around. Defaults to 5.
+    #[inline]
+    pub fn keep(mut self, keep: usize) -> Self {
+        self.keep = keep;
+        self
+    }
+
+    /// Sets the [`Format`] logs are written in.
+    #[inline]
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// The I/O errors hit by this sink. See [`ErrorCount`].
+    #[inline]
+    pub fn errors(&self) -> ErrorCount {
+        self.errors.clone()
+    }
+
+    /// When the current file was last modified, if it isn't empty.
+    fn modified(&self) -> Option<DateTime<Utc>> {
+        let metadata = self.file.as_ref()?.get_ref().metadata().ok()?;
+        (metadata.len() > 0)
+            .then(|| metadata.modified().ok().map(DateTime::<Utc>::from))
+            .flatten()
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        self.size = file.metadata()?.len();
+        self.file = Some(BufWriter::new(file));
+
+        Ok(())
+    }
+
+    /// The path of the `index`th archive.
+    fn archive(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+
+        if self.keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.keep).rev() {
+                let archive = self.archive(index);
+                if archive.exists() {
+                    std::fs::rename(archive, self.archive(index + 1))?;
+                }
+            }
+
+            std::fs::rename(&self.path, self.archive(1))?;
+        }
+
+        self.open()
+    }
+
+    fn write(&mut self, log: &Log) -> io::Result<()> {
+        write_line(&mut self.line, log, &self.format);
+
+        let period = self.rotation.period(log.time);
+        let crossed_period = self.period.is_some() && period != self.period;
+        let too_big = self
+            .max_size
+            .is_some_and(|max_size| self.size + self.line.len() as u64 > max_size);
+
+        if self.size > 0 && (crossed_period || too_big) {
+            self.rotate()?;
+        } else if self.file.is_none() {
+            self.open()?;
+        }
+
+        let file = self.file.as_mut().expect("file is open");
+        file.write_all(self.line.as_bytes())?;
+        self.size += self.line.len() as u64;
+        self.period = period;
+
+        Ok(())
+    }
+}
+
+impl Sink for FileSink {
+    fn accept(&mut self, log: &Log) {
+        let result = self.write(log);
+        if self.errors.track(result).is_err() {
+            // reopen on the next log
+            self.file = None;
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some(file) = &mut self.file {
+            let _ = self.errors.track(file.flush());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FileSink, Rotation};
+    use crate::{test::test_log, Sink};
+    use chrono::{TimeZone, Utc};
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("qlog-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(path: PathBuf) -> Vec<String> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| line.rsplit(' ').next().unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn size_rotation() {
+        let dir = temp_dir("size");
+        let path = dir.join("emu.log");
+        let time = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let line = format!("{}\n", test_log("0", Vec::new()).at(time));
+
+        let mut sink = FileSink::new(&path)
+            .unwrap()
+            .max_size(2 * line.len() as u64)
+            .keep(2);
+        for i in 0..7 {
+            sink.accept(&test_log(&i.to_string(), Vec::new()).at(time));
+        }
+        sink.flush();
+
+        assert_eq!(read(path.clone()), ["6"]);
+        assert_eq!(read(dir.join("emu.log.1")), ["4", "5"]);
+        assert_eq!(read(dir.join("emu.log.2")), ["2", "3"]);
+        assert!(!dir.join("emu.log.3").exists());
+
+        // appends to the existing file
+        std::mem::drop(sink);
+        let mut sink = FileSink::new(&path).unwrap();
+        sink.accept(&test_log("7", Vec::new()).at(time));
+        sink.flush();
+        assert_eq!(read(path), ["6", "7"]);
+    }
+
+    #[test]
+    fn time_rotation() {
+        let dir = temp_dir("time");
+        let path = dir.join("emu.log");
+        let time = Utc.with_ymd_and_hms(2024, 3, 1, 23, 0, 0).unwrap();
+
+        let mut sink = FileSink::new(&path).unwrap().rotation(Rotation::Daily);
+        sink.accept(&test_log("a", Vec::new()).at(time));
+        sink.accept(&test_log("b", Vec::new()).at(time + chrono::Duration::minutes(59)));
+        sink.accept(&test_log("c", Vec::new()).at(time + chrono::Duration::minutes(61)));
+        sink.flush();
+
+        assert_eq!(read(path), ["c"]);
+        assert_eq!(read(dir.join("emu.log.1")), ["a", "b"]);
+    }
+}
@@ -0,0 +1,56 @@
+use super::{ErrorCount, Sink};
+use crate::Log;
+use std::io::{self, Write};
+
+/// A [`Sink`] which writes logs as [JSON Lines](https://jsonlines.org), one JSON object per log.
+///
+/// Every log is written with a separate call to the writer, so wrap it in an
+/// [`io::BufWriter`] if that's expensive.
+pub struct JsonLinesSink<W> {
+    writer: W,
+    errors: ErrorCount,
+}
+
+impl<W> JsonLinesSink<W>
+where
+    W: Write,
+{
+    #[inline]
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            errors: ErrorCount::default(),
+        }
+    }
+
+    /// The I/O errors hit by this sink. See [`ErrorCount`].
+    #[inline]
+    pub fn errors(&self) -> ErrorCount {
+        self.errors.clone()
+    }
+
+    /// Consumes the sink, returning the writer.
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Writes `log` as a line of JSON.
+pub(crate) fn write_line(mut writer: impl Write, log: &Log) -> io::Result<()> {
+    serde_json::to_writer(&mut writer, log)?;
+    writer.write_all(b"\n")
+}
+
+impl<W> Sink for JsonLinesSink<W>
+where
+    W: Write + Send,
+{
+    fn accept(&mut self, log: &Log) {
+        let _ = self.errors.track(write_line(&mut self.writer, log));
+    }
+
+    fn flush(&mut self) {
+        let _ = self.errors.track(self.writer.flush());
+    }
+}
@@ -0,0 +1,51 @@
+use super::{ErrorCount, Sink};
+use crate::Log;
+use std::io::Write;
+
+/// A [`Sink`] which writes logs in [logfmt](https://brandur.org/logfmt), one per line. See
+/// [`Log::logfmt`].
+///
+/// Every log is written with a separate call to the writer, so wrap it in an
+/// [`io::BufWriter`](std::io::BufWriter) if that's expensive.
+pub struct LogfmtSink<W> {
+    writer: W,
+    errors: ErrorCount,
+}
+
+impl<W> LogfmtSink<W>
+where
+    W: Write,
+{
+    #[inline]
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            errors: ErrorCount::default(),
+        }
+    }
+
+    /// The I/O errors hit by this sink. See [`ErrorCount`].
+    #[inline]
+    pub fn errors(&self) -> ErrorCount {
+        self.errors.clone()
+    }
+
+    /// Consumes the sink, returning the writer.
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W> Sink for LogfmtSink<W>
+where
+    W: Write + Send,
+{
+    fn accept(&mut self, log: &Log) {
+        let _ = self.errors.track(writeln!(self.writer, "{}", log.logfmt()));
+    }
+
+    fn flush(&mut self) {
+        let _ = self.errors.track(self.writer.flush());
+    }
+}
@@ -0,0 +1,106 @@
+use super::Sink;
+use crate::{Log, Logs};
+use std::sync::{Arc, Mutex};
+
+/// The built-in sink which keeps the history of a logger in memory.
+pub(crate) struct Memory {
+    pub logs: Arc<Mutex<Logs>>,
+    pub limit: Option<usize>,
+    pub budget: Option<usize>,
+    pub dedupe: bool,
+}
+
+impl Memory {
+    /// Makes room for a new log, unless it's a repetition of the last one or it doesn't fit in the
+    /// budget on its own. Returns whether it should be stored.
+    fn prepare(&self, logs: &mut Logs, log: &Log) -> bool {
+        if self.dedupe && logs.repeat_last(log) {
+            return false;
+        }
+
+        if let Some(budget) = self.budget {
+            let size = Logs::size_of(log);
+            if size > budget {
+                return false;
+            }
+
+            while logs.memory_usage() + size > budget {
+                logs.pop_front();
+            }
+        }
+
+        if self.limit.is_some_and(|limit| logs.len() == limit) {
+            logs.pop_front();
+        }
+
+        true
+    }
+
+    /// Like [`Sink::accept`], but takes ownership of the log. The backing thread hands logs which
+    /// are only formatted when read to the in-memory history this way, since it's always the last
+    /// to see them, so that they're stored as is instead of being formatted to be copied.
+    pub fn accept_owned(&mut self, log: Log) {
+        if self.limit == Some(0) {
+            return;
+        }
+
+        let mut logs = self.logs.lock().expect("lock is not poisoned");
+        if self.prepare(&mut logs, &log) {
+            logs.push_owned(log);
+        }
+    }
+}
+
+impl Sink for Memory {
+    fn accept(&mut self, log: &Log) {
+        if self.limit == Some(0) {
+            return;
+        }
+
+        let mut logs = self.logs.lock().expect("lock is not poisoned");
+        if self.prepare(&mut logs, log) {
+            logs.push(log);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Memory;
+    use crate::{test::test_log, Logs, Sink};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    #[test]
+    fn accept_owned_keeps_logs_unformatted() {
+        let mut memory = Memory {
+            logs: Arc::new(Mutex::new(Logs::default())),
+            limit: None,
+            budget: None,
+            dedupe: false,
+        };
+
+        let formatted = Arc::new(AtomicUsize::new(0));
+        let lazy = || {
+            let formatted = formatted.clone();
+            test_log("", Vec::new()).deferred(move |w: &mut dyn std::fmt::Write| {
+                formatted.fetch_add(1, Ordering::Relaxed);
+                w.write_str("lazy")
+            })
+        };
+
+        memory.accept_owned(lazy());
+        assert_eq!(formatted.load(Ordering::Relaxed), 0);
+
+        // a borrowed log has to be formatted to be copied
+        memory.accept(&lazy());
+        assert_eq!(formatted.load(Ordering::Relaxed), 1);
+
+        let logs = memory.logs.lock().unwrap();
+        assert_eq!(logs[0].message(), "lazy");
+        assert_eq!(logs[1].message(), "lazy");
+        assert_eq!(formatted.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,84 @@
+mod binary;
+mod console;
+mod file;
+#[cfg(feature = "serde")]
+mod json;
+mod logfmt;
+mod memory;
+
+pub use binary::BinaryWriter;
+pub use console::ConsoleSink;
+pub use file::{FileSink, Rotation};
+#[cfg(feature = "serde")]
+pub(crate) use json::write_line as write_json_line;
+#[cfg(feature = "serde")]
+pub use json::JsonLinesSink;
+pub use logfmt::LogfmtSink;
+pub(crate) use memory::Memory;
+
+use crate::Log;
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// A destination for processed [`Log`]s. Sinks live in the backing thread of a logger, which hands
+/// every log it processes to each of its sinks, in the order they were added.
+///
+/// Every logger keeps an in-memory history of logs, accessible through
+/// [`Logger::with_logs`](crate::Logger::with_logs), which is itself a sink - always the last one.
+///
+/// Since sinks run on the backing thread, they have no way of returning errors to the code doing
+/// the logging. The built-in sinks which do I/O carry on after a failed write, losing whatever
+/// couldn't be written, and count the failure in an [`ErrorCount`] obtained from their `errors`
+/// method.
+pub trait Sink: Send {
+    /// Accepts a processed log.
+    fn accept(&mut self, log: &Log);
+
+    /// Flushes any output buffered by this sink. Called when the logger is
+    /// [flushed](crate::Logger::flush) or shut down.
+    #[inline]
+    fn flush(&mut self) {}
+}
+
+impl<S> Sink for Box<S>
+where
+    S: Sink + ?Sized,
+{
+    #[inline]
+    fn accept(&mut self, log: &Log) {
+        (**self).accept(log);
+    }
+
+    #[inline]
+    fn flush(&mut self) {
+        (**self).flush();
+    }
+}
+
+/// A count of the I/O errors hit by a [`Sink`]. It's shared with the sink it comes from, so it can
+/// still be read after the sink is handed to a logger.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorCount(Arc<AtomicU64>);
+
+impl ErrorCount {
+    /// How many errors were hit so far.
+    #[inline]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Counts `result` if it's an error.
+    #[inline]
+    pub(crate) fn track<T>(&self, result: io::Result<T>) -> io::Result<T> {
+        if result.is_err() {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+
+        result
+    }
+}
@@ -0,0 +1,266 @@
+use crate::{
+    builder::TimeSource, content::Content, interned::Packed, level::AtomicLevel,
+    loggable::ErasedLoggable, sink::Memory, Backpressure, Callsite, Level, Log, LogError,
+    LoggerBuilder, Sink,
+};
+use flume::{SendTimeoutError, TrySendError};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::{JoinHandle, ThreadId},
+};
+
+pub(crate) struct LogBuilder {
+    pub level: Level,
+    pub callsite: &'static Callsite,
+    pub payload: Payload,
+}
+
+/// What the message of a log is made from.
+pub(crate) enum Payload {
+    Loggable(ErasedLoggable),
+    Packed(Packed),
+}
+
+/// A message sent from a [`Logger`](crate::Logger) to its backing thread.
+pub(crate) enum Message {
+    Log(LogBuilder),
+    /// A barrier: the backing thread signals the sender once every message queued before it has
+    /// been processed.
+    Flush(flume::Sender<()>),
+    /// Adds a sink to the backing thread, which sees every log queued after this message.
+    AddSink(Box<dyn Sink>),
+    /// Stops the backing thread once every message queued before it has been processed.
+    Shutdown,
+}
+
+/// Handle to the backing thread of a logger. It is shared by all clones of a logger, and dropping
+/// it shuts the thread down.
+pub(crate) struct Worker {
+    pub sender: flume::Sender<Message>,
+    pub min_level: AtomicLevel,
+    backpressure: Backpressure,
+    /// A second receiver for the channel, used by [`Backpressure::DropOldest`] to discard queued
+    /// messages. The backing thread clears it when it stops so that the channel disconnects.
+    evictor: Arc<Mutex<Option<flume::Receiver<Message>>>>,
+    dropped: AtomicU64,
+    thread_id: ThreadId,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Worker {
+    pub fn spawn(memory: Memory, config: LoggerBuilder) -> Self {
+        let (sender, receiver) = flume::bounded::<Message>(config.channel_capacity);
+        let evictor = Arc::new(Mutex::new(
+            (config.backpressure == Backpressure::DropOldest).then(|| receiver.clone()),
+        ));
+
+        let mut thread = std::thread::Builder::new();
+        if let Some(name) = config.thread_name {
+            thread = thread.name(name);
+        }
+
+        let thread = thread
+            .spawn({
+                let evictor = ClearOnDrop(evictor.clone());
+                let sinks = config.sinks;
+                let time_source = config.time_source;
+                let deferred = config.deferred_formatting;
+                move || {
+                    run(&receiver, memory, sinks, &time_source, deferred);
+                    std::mem::drop(evictor);
+                }
+            })
+            .expect("failed to spawn logger thread");
+
+        Self {
+            sender,
+            min_level: AtomicLevel::new(config.min_level),
+            backpressure: config.backpressure,
+            evictor,
+            dropped: AtomicU64::new(0),
+            thread_id: thread.thread().id(),
+            thread: Mutex::new(Some(thread)),
+        }
+    }
+
+    /// Sends a log to the backing thread, following the [`Backpressure`] policy if the channel is
+    /// full. Logs sent after the backing thread is gone are discarded.
+    #[inline]
+    pub fn send_log(&self, builder: LogBuilder) {
+        let message = Message::Log(builder);
+        match self.backpressure {
+            Backpressure::Block => {
+                let _ = self.sender.send(message);
+            }
+            Backpressure::DropNewest => {
+                if let Err(TrySendError::Full(_)) = self.sender.try_send(message) {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            Backpressure::DropOldest => self.send_evicting(message),
+            Backpressure::Timeout(timeout) => {
+                if let Err(SendTimeoutError::Timeout(_)) =
+                    self.sender.send_timeout(message, timeout)
+                {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Sends a log to the backing thread without blocking.
+    #[inline]
+    pub fn try_send_log(&self, builder: LogBuilder) -> Result<(), LogError> {
+        self.sender
+            .try_send(Message::Log(builder))
+            .map_err(|e| match e {
+                TrySendError::Full(_) => LogError::QueueFull,
+                TrySendError::Disconnected(_) => LogError::WorkerDead,
+            })
+    }
+
+    #[cold]
+    fn send_evicting(&self, message: Message) {
+        // control messages can't be discarded, so evicted ones are requeued after the message
+        // being sent. moving a barrier or a shutdown further back is fine, as it only means more
+        // logs get processed before it
+        let mut pending = VecDeque::from([message]);
+        while let Some(message) = pending.pop_front() {
+            match self.sender.try_send(message) {
+                Err(TrySendError::Full(message)) => pending.push_front(message),
+                Err(TrySendError::Disconnected(_)) => return,
+                Ok(()) => continue,
+            }
+
+            let evicted = {
+                let evictor = self.evictor.lock().expect("lock is not poisoned");
+                let Some(evictor) = evictor.as_ref() else {
+                    return;
+                };
+
+                evictor.try_recv()
+            };
+
+            match evicted {
+                Ok(Message::Log(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(control) => pending.push_back(control),
+                // the backing thread made room in the meantime
+                Err(_) => (),
+            }
+        }
+    }
+
+    /// How many logs were discarded because of the [`Backpressure`] policy.
+    #[inline]
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Stops the backing thread after it processes every message queued so far and waits for it
+    /// to finish. If another thread is already shutting it down, waits for that instead. Does
+    /// nothing if it has already been shut down.
+    pub fn shutdown(&self) {
+        // a loggable might own the last clone of a logger, in which case we're being dropped by
+        // the backing thread itself: it can't wait for itself, and closing the channel is enough
+        // to make it stop
+        if self.thread_id == std::thread::current().id() {
+            return;
+        }
+
+        // the lock is held until the thread is joined, so that concurrent callers wait for it too
+        let mut thread = self.thread.lock().expect("lock is not poisoned");
+        let Some(thread) = thread.take() else {
+            return;
+        };
+
+        if self.sender.send(Message::Shutdown).is_ok() {
+            // if the thread panicked, there's nothing left to do
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Clears the evictor of a [`Worker`] when dropped, even if the backing thread panics.
+struct ClearOnDrop(Arc<Mutex<Option<flume::Receiver<Message>>>>);
+
+impl Drop for ClearOnDrop {
+    fn drop(&mut self) {
+        self.0.lock().expect("lock is not poisoned").take();
+    }
+}
+
+fn run(
+    receiver: &flume::Receiver<Message>,
+    mut memory: Memory,
+    mut sinks: Vec<Box<dyn Sink>>,
+    now: &TimeSource,
+    deferred: bool,
+) {
+    // logs formatted upfront are formatted into this one, so that its memory is reused. the
+    // in-memory history copies them into its own storage
+    let mut scratch: Option<Log> = None;
+
+    while let Ok(message) = receiver.recv() {
+        let builder = match message {
+            Message::Log(builder) => builder,
+            Message::Flush(done) => {
+                sinks.iter_mut().for_each(|sink| sink.flush());
+
+                // the flusher might have given up waiting already
+                let _ = done.send(());
+                continue;
+            }
+            Message::AddSink(sink) => {
+                sinks.push(sink);
+                continue;
+            }
+            Message::Shutdown => break,
+        };
+
+        let time = now();
+        let Callsite { target, location } = *builder.callsite;
+
+        let content = match builder.payload {
+            Payload::Loggable(loggable) if deferred => Content::deferred(loggable),
+            Payload::Loggable(loggable) => {
+                let log = match &mut scratch {
+                    Some(log) => {
+                        log.reset(time, builder.level, target, location);
+                        log.content.reformat(loggable);
+                        log
+                    }
+                    None => scratch.insert(Log::with_content(
+                        time,
+                        builder.level,
+                        target,
+                        location,
+                        Content::format(loggable),
+                    )),
+                };
+
+                sinks.iter_mut().for_each(|sink| sink.accept(log));
+                memory.accept(log);
+                continue;
+            }
+            Payload::Packed(packed) => Content::packed(packed),
+        };
+
+        let log = Log::with_content(time, builder.level, target, location, content);
+        sinks.iter_mut().for_each(|sink| sink.accept(&log));
+        memory.accept_owned(log);
+    }
+
+    sinks.iter_mut().for_each(|sink| sink.flush());
+}